@@ -0,0 +1,145 @@
+use crate::{ errors::ProcessorError, repair::repair_json };
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+
+/// Default alt text length; screen readers commonly cut off or page longer text.
+pub const DEFAULT_ALT_TEXT_LIMIT: usize = 125;
+
+/// Alt text and long description for publishing an image.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilityDescription {
+    /// Short replacement text for the `alt` attribute, empty for decorative images
+    pub alt_text: String,
+    /// Full description for complex images such as charts, maps and diagrams
+    pub long_description: String,
+    /// Purely decorative images should get an empty `alt` attribute
+    pub decorative: bool,
+    /// The image contains text, which should also be available as real text
+    pub text_in_image: bool,
+    /// Text shown in the image, verbatim
+    pub image_text: String,
+    /// Text or important elements whose contrast against the background looks too low
+    pub contrast_issues: Vec<String>,
+    /// The model's alt text exceeded the limit and was shortened
+    #[serde(skip_deserializing)]
+    pub alt_text_shortened: bool,
+    /// `builtin`, or the `name@version` of the prompt template used
+    #[serde(skip_deserializing)]
+    pub prompt_version: String,
+}
+
+/// Instructions for the accessibility mode, following the WCAG text alternative guidance.
+pub fn prompt(alt_text_limit: usize) -> String {
+    format!(
+        r#"Write text alternatives for this image as an accessibility specialist following WCAG 2.2.
+
+- alt_text: at most {} characters. Convey the content and purpose of the image, not its appearance. Do not start with "image of" or "picture of". Leave it empty when the image is purely decorative.
+- long_description: for images whose information does not fit in the alt text, such as charts, diagrams, maps or infographics, describe all of it in full sentences, including data and relationships. Leave it empty for simple images.
+- decorative: true only if the image adds no information.
+- text_in_image and image_text: whether the image contains text, and that text verbatim.
+- contrast_issues: name each piece of text or important element whose contrast against its background looks lower than 4.5:1 for normal text or 3:1 for large text and graphics.
+
+Respond with JSON only:
+{{
+    "alt_text": "string",
+    "long_description": "string",
+    "decorative": true|false,
+    "text_in_image": true|false,
+    "image_text": "string",
+    "contrast_issues": ["string"]
+}}"#,
+        alt_text_limit
+    )
+}
+
+/// JSON Schema for [`AccessibilityDescription`], for providers that enforce schemas.
+pub fn schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "alt_text": { "type": "string" },
+            "long_description": { "type": "string" },
+            "decorative": { "type": "boolean" },
+            "text_in_image": { "type": "boolean" },
+            "image_text": { "type": "string" },
+            "contrast_issues": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": [
+            "alt_text",
+            "long_description",
+            "decorative",
+            "text_in_image",
+            "image_text",
+            "contrast_issues"
+        ],
+        "additionalProperties": false
+    })
+}
+
+impl AccessibilityDescription {
+    /// Parses provider output, shortening the alt text to `alt_text_limit` characters.
+    pub fn parse(output: &str, alt_text_limit: usize) -> Result<Self, ProcessorError> {
+        let (json, _) = repair_json(output);
+        let mut description: Self = serde_json::from_str(&json).map_err(|e| {
+            ProcessorError::ResponseParseError(
+                format!("Failed to parse accessibility description: {}. Response text: {}", e, output)
+            )
+        })?;
+        description.alt_text = description.alt_text.trim().to_string();
+        if description.alt_text.chars().count() > alt_text_limit {
+            description.alt_text = shorten(&description.alt_text, alt_text_limit);
+            description.alt_text_shortened = true;
+        }
+        if description.image_text.trim().is_empty() {
+            description.image_text.clear();
+        } else {
+            description.text_in_image = true;
+        }
+        Ok(description)
+    }
+}
+
+/// Cuts text to at most `limit` characters, at a word boundary when there is one,
+/// ending with an ellipsis.
+fn shorten(text: &str, limit: usize) -> String {
+    if limit == 0 {
+        return String::new();
+    }
+    let cut = text
+        .char_indices()
+        .nth(limit - 1)
+        .map_or(text.len(), |(i, _)| i);
+    let head = &text[..cut];
+    // Drop the last word unless it ends right at the cut
+    let head = match head.rfind(char::is_whitespace) {
+        Some(space) if space > 0 && !text[cut..].starts_with(char::is_whitespace) => &head[..space],
+        _ => head,
+    };
+    format!("{}…", head.trim_end_matches(|c: char| c.is_whitespace() || ",;:.-".contains(c)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alt_text_is_shortened_at_word_boundary() {
+        let output =
+            r#"{"alt_text": "A red bicycle leaning against a brick wall, next to a door", "image_text": "OPEN"}"#;
+        let description = AccessibilityDescription::parse(output, 30).unwrap();
+        assert_eq!(description.alt_text, "A red bicycle leaning against…");
+        assert!(description.alt_text.chars().count() <= 30);
+        assert!(description.alt_text_shortened);
+        assert!(description.text_in_image);
+    }
+
+    #[test]
+    fn test_short_alt_text_is_kept() {
+        let output = "```json\n{\"alt_text\": \"Team photo\", \"decorative\": false}\n```";
+        let description = AccessibilityDescription::parse(output, DEFAULT_ALT_TEXT_LIMIT).unwrap();
+        assert_eq!(description.alt_text, "Team photo");
+        assert!(!description.alt_text_shortened);
+        assert!(!description.text_in_image);
+    }
+}
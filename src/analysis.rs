@@ -0,0 +1,311 @@
+use crate::{ errors::ProcessorError, tables::Table, utils::BoundingBox };
+use serde::{ Deserialize, Serialize };
+
+/// Typed form of the output requested by `PromptFormat::Json`.
+///
+/// Sections switched off in `AnalysisConfig` are simply left empty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalysisResult {
+    pub classification: Classification,
+    pub content: Content,
+    pub analysis: Analysis,
+    pub extracted_data: ExtractedData,
+    pub insights: Insights,
+    /// Category-specific fields, present when the prompt targeted a known category.
+    pub category_details: Option<serde_json::Value>,
+    pub dynamic_extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Classification {
+    pub primary_category: String,
+    pub secondary_categories: Vec<String>,
+    pub confidence: f32,
+    pub discovered_categories: Vec<DiscoveredCategory>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoveredCategory {
+    pub name: String,
+    pub confidence: f32,
+    pub reasoning: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Content {
+    pub main_elements: Vec<Element>,
+    pub people: Vec<Person>,
+    pub brands: Vec<Brand>,
+    pub context: Context,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Element {
+    #[serde(rename = "type")]
+    pub element_type: String,
+    pub description: String,
+    /// Object class, present when object detection was requested
+    pub label: Option<String>,
+    /// Position as fractions of the image size, present when object detection was requested
+    pub bbox: Option<BoundingBox>,
+    pub location: Option<String>,
+    pub relationships: Vec<Relationship>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Relationship {
+    pub related_to: String,
+    #[serde(rename = "type")]
+    pub relationship_type: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Person {
+    pub description: String,
+    pub expression: Option<String>,
+    pub location: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Brand {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub brand_type: String,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Context {
+    pub setting: String,
+    pub purpose: String,
+    pub time_period: Option<String>,
+    pub cultural_context: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Analysis {
+    pub visual: Option<Visual>,
+    pub semantic: Option<Semantic>,
+    pub technical: Option<Technical>,
+    pub accessibility: Option<Accessibility>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Visual {
+    pub composition: Option<Composition>,
+    pub colors: Vec<Color>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Composition {
+    pub layout: String,
+    pub style: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Color {
+    pub name: String,
+    pub hex: String,
+    pub dominance: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Semantic {
+    pub themes: Vec<String>,
+    pub emotional_tone: Option<EmotionalTone>,
+    pub symbolism: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmotionalTone {
+    pub primary: String,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Symbol {
+    pub symbol: String,
+    pub meaning: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Technical {
+    pub quality: String,
+    pub creation_method: String,
+    pub notable_characteristics: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Accessibility {
+    pub alt_text: String,
+    pub contains_text: bool,
+    pub contrast_issues: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractedData {
+    pub text: Vec<TextItem>,
+    pub data_points: Vec<DataPoint>,
+    pub tables: Vec<Table>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextItem {
+    /// The text as written in the image
+    pub content: String,
+    pub location: String,
+    pub purpose: String,
+    /// ISO 639-1 code of the language the text is written in, when translation is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// The text in the requested language, when translation is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translation: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DataPoint {
+    #[serde(rename = "type")]
+    pub data_type: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Insights {
+    pub key_observations: Vec<String>,
+    pub unusual_elements: Vec<String>,
+    pub suggestions: Vec<String>,
+    pub patterns: Vec<String>,
+}
+
+impl AnalysisResult {
+    /// Parses provider output and validates it against the schema constraints.
+    pub fn parse(output: &str) -> Result<Self, ProcessorError> {
+        let result: AnalysisResult = serde_json::from_str(output).map_err(|e| {
+            if e.is_data() {
+                ProcessorError::SchemaViolation(vec![e.to_string()])
+            } else {
+                ProcessorError::ResponseParseError(format!("Analysis is not valid JSON: {}", e))
+            }
+        })?;
+
+        let violations = result.violations();
+        if !violations.is_empty() {
+            return Err(ProcessorError::SchemaViolation(violations));
+        }
+        Ok(result)
+    }
+
+    /// Lists every constraint the result breaks, each prefixed with its JSON path.
+    pub fn violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        let mut confidence = |path: String, value: f32| {
+            if !(0.0..=1.0).contains(&value) {
+                violations.push(format!("{}: {} is not between 0.0 and 1.0", path, value));
+            }
+        };
+
+        confidence("classification.confidence".into(), self.classification.confidence);
+        for (i, category) in self.classification.discovered_categories.iter().enumerate() {
+            confidence(
+                format!("classification.discovered_categories[{}].confidence", i),
+                category.confidence
+            );
+        }
+        for (i, brand) in self.content.brands.iter().enumerate() {
+            confidence(format!("content.brands[{}].confidence", i), brand.confidence);
+        }
+        if let Some(tone) = self.analysis.semantic.as_ref().and_then(|s| s.emotional_tone.as_ref()) {
+            confidence("analysis.semantic.emotional_tone.confidence".into(), tone.confidence);
+        }
+        let colors = self.analysis.visual
+            .as_ref()
+            .map(|v| v.colors.as_slice())
+            .unwrap_or_default();
+        for (i, color) in colors.iter().enumerate() {
+            confidence(format!("analysis.visual.colors[{}].dominance", i), color.dominance);
+        }
+
+        for (i, color) in colors.iter().enumerate() {
+            if !is_hex_color(&color.hex) {
+                violations.push(
+                    format!("analysis.visual.colors[{}].hex: {:?} is not #RRGGBB", i, color.hex)
+                );
+            }
+        }
+
+        violations
+    }
+}
+
+fn is_hex_color(value: &str) -> bool {
+    value.len() == 7 &&
+        value.starts_with('#') &&
+        value[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_valid_result() {
+        let output =
+            r##"{
+            "classification": { "primary_category": "photo", "confidence": 0.9 },
+            "analysis": { "visual": { "colors": [{ "name": "red", "hex": "#FF0000", "dominance": 0.5 }] } },
+            "extracted_data": { "text": [{ "content": "SALE", "location": "top", "purpose": "banner" }] }
+        }"##;
+        let result = AnalysisResult::parse(output).unwrap();
+        assert_eq!(result.classification.primary_category, "photo");
+        assert_eq!(result.extracted_data.text[0].content, "SALE");
+    }
+
+    #[test]
+    fn test_schema_violations() {
+        let output =
+            r##"{
+            "classification": { "confidence": 1.5 },
+            "analysis": { "visual": { "colors": [{ "hex": "red", "dominance": 0.2 }] } }
+        }"##;
+        match AnalysisResult::parse(output) {
+            Err(ProcessorError::SchemaViolation(violations)) => {
+                assert_eq!(violations.len(), 2);
+                assert!(violations[0].starts_with("classification.confidence"));
+                assert!(violations[1].starts_with("analysis.visual.colors[0].hex"));
+            }
+            other => panic!("expected schema violation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_wrong_types_are_schema_violations() {
+        let output = r#"{ "classification": { "confidence": "high" } }"#;
+        assert!(matches!(AnalysisResult::parse(output), Err(ProcessorError::SchemaViolation(_))));
+        assert!(
+            matches!(AnalysisResult::parse("not json"), Err(ProcessorError::ResponseParseError(_)))
+        );
+    }
+}
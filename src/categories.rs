@@ -0,0 +1,245 @@
+use crate::{ extraction, prompts::ContentCategory };
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+
+/// Snake-case names of every fixed `ContentCategory`, as offered to the classifier.
+pub const CATEGORY_NAMES: [&str; 46] = [
+    "screenshot",
+    "user_interface",
+    "social_media_post",
+    "digital_art",
+    "website",
+    "software",
+    "video_game",
+    "document",
+    "receipt",
+    "business_card",
+    "invoice",
+    "form",
+    "identification",
+    "certificate",
+    "photo",
+    "artwork",
+    "illustration",
+    "meme",
+    "comic",
+    "advertisement",
+    "poster",
+    "recipe",
+    "tutorial",
+    "diagram",
+    "blueprint",
+    "schematic",
+    "manual",
+    "guide",
+    "chart",
+    "graph",
+    "dashboard",
+    "infographic",
+    "timeline",
+    "flowchart",
+    "mind_map",
+    "map",
+    "floor_plan",
+    "architecture",
+    "landscape",
+    "satellite",
+    "medical",
+    "scientific",
+    "technical",
+    "educational",
+    "legal",
+    "financial",
+];
+
+/// Minimum classifier confidence for the specialized stage to trust the category.
+pub const MIN_CLASSIFICATION_CONFIDENCE: f32 = 0.5;
+
+/// Output of the classification stage.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CategoryClassification {
+    pub category: String,
+    pub confidence: f32,
+    pub platform: Option<String>,
+    pub traits: Vec<String>,
+}
+
+impl CategoryClassification {
+    /// Maps the classifier's answer onto a `ContentCategory`, falling back to
+    /// `Discovered` for names outside the fixed list.
+    pub fn into_category(self) -> ContentCategory {
+        match ContentCategory::from_name(&self.category) {
+            Some(ContentCategory::Screenshot { .. }) => ContentCategory::Screenshot {
+                platform: self.platform,
+            },
+            Some(category) => category,
+            None => ContentCategory::Discovered {
+                name: self.category.trim().to_lowercase().replace([' ', '-'], "_"),
+                confidence: self.confidence,
+                traits: self.traits,
+            },
+        }
+    }
+}
+
+/// Prompt for the cheap first stage that only picks a category.
+pub fn classification_prompt() -> String {
+    format!(
+        r#"Classify this image into exactly one content category.
+
+Known categories: {}
+
+If none fits, invent a short snake_case category name and list the traits that define it.
+
+Respond with JSON only:
+{{
+    "category": "string",
+    "confidence": 0.0-1.0,
+    "platform": "string or null, for screenshots only",
+    "traits": ["string"]
+}}"#,
+        CATEGORY_NAMES.join(", ")
+    )
+}
+
+/// JSON Schema for [`CategoryClassification`], for providers that enforce schemas.
+pub fn classification_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "category": { "type": "string" },
+            "confidence": { "type": "number", "minimum": 0.0, "maximum": 1.0 },
+            "platform": { "type": ["string", "null"] },
+            "traits": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": ["category", "confidence", "platform", "traits"],
+        "additionalProperties": false
+    })
+}
+
+/// What the specialized stage should pay attention to for a category.
+pub fn focus(category: &ContentCategory) -> Option<String> {
+    use ContentCategory::*;
+
+    let focus = match category {
+        Screenshot { .. } | UserInterface | Website | Software =>
+            "the application or site shown, visible UI elements and their state, and all on-screen text",
+        SocialMediaPost => "the author, post text, hashtags, mentions and engagement counts",
+        Document | Manual | Guide | Legal | Certificate =>
+            "the document type, title, sections, key statements, dates and parties involved",
+        Receipt | Invoice | Financial =>
+            "the merchant or issuer, dates, every line item with amounts, taxes, totals and currency",
+        BusinessCard => "the person, role, organization and every contact detail",
+        Form => "every field label together with its filled-in value",
+        Identification => "the document type, issuing authority and every printed field",
+        Recipe => "the ingredients with quantities, preparation steps, timings and servings",
+        Tutorial | Educational => "the subject taught, the steps or concepts shown, and their order",
+        Chart | Graph | Dashboard | Infographic =>
+            "the chart type, axis labels and ranges, series names and the plotted values",
+        Timeline => "every event with its date, in chronological order",
+        Diagram | Flowchart | MindMap | Schematic | Blueprint =>
+            "every node or component, its label, and how the parts connect",
+        Map | FloorPlan | Satellite => "the area covered, labeled places, scale and orientation",
+        Advertisement | Poster => "the advertised product or event, slogans, brands and call to action",
+        Meme | Comic => "the caption text, the panels, and the joke or message",
+        Medical | Scientific | Technical =>
+            "the subject, measurements, annotations and any domain-specific findings",
+        Discovered { name, traits, .. } => {
+            return Some(
+                if traits.is_empty() {
+                    format!("the details that matter for a {} image", name)
+                } else {
+                    format!("the details that matter for a {} image: {}", name, traits.join(", "))
+                }
+            );
+        }
+        Photo | Artwork | Illustration | DigitalArt | VideoGame | Architecture | Landscape => {
+            return None;
+        }
+    };
+    Some(focus.to_string())
+}
+
+/// Extra fields requested under `category_details` for categories that have them.
+pub fn details_structure(category: &ContentCategory) -> Option<Value> {
+    use ContentCategory::*;
+
+    if let Some(structure) = extraction::structure(category) {
+        return Some(structure);
+    }
+    let details = match category {
+        Recipe =>
+            json!({
+                "title": "string",
+                "servings": "string",
+                "ingredients": [{ "name": "string", "quantity": "string" }],
+                "steps": ["string"]
+            }),
+        Discovered { traits, .. } if !traits.is_empty() => {
+            Value::Object(
+                traits
+                    .iter()
+                    .map(|t| (t.clone(), json!("string")))
+                    .collect()
+            )
+        }
+        _ => {
+            return None;
+        }
+    };
+    Some(details)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_names_roundtrip() {
+        for name in CATEGORY_NAMES {
+            let classification = CategoryClassification {
+                category: name.to_string(),
+                confidence: 0.9,
+                ..Default::default()
+            };
+            assert!(
+                !matches!(classification.into_category(), ContentCategory::Discovered { .. }),
+                "{} did not map to a fixed category",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_category_is_discovered() {
+        let classification = CategoryClassification {
+            category: "Parking Ticket".to_string(),
+            confidence: 0.7,
+            platform: None,
+            traits: vec!["plate_number".to_string()],
+        };
+        match classification.into_category() {
+            ContentCategory::Discovered { name, traits, .. } => {
+                assert_eq!(name, "parking_ticket");
+                assert_eq!(traits, vec!["plate_number"]);
+            }
+            other => panic!("expected discovered category, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_screenshot_keeps_platform() {
+        let classification = CategoryClassification {
+            category: "screenshot".to_string(),
+            platform: Some("ios".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            matches!(
+                classification.into_category(),
+                ContentCategory::Screenshot { platform: Some(p) } if p == "ios"
+            )
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    errors::ProcessorError,
+    extraction::{ parse_details, CategoryExtraction },
+    prompts::{ ContentCategory, NULLABLE_NUMBER },
+    utils::csv_field,
+};
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+
+/// Data read off a single chart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChartData {
+    pub title: String,
+    /// `bar`, `line`, `pie`, `scatter`, `area`, ...
+    pub chart_type: String,
+    pub x_axis: Axis,
+    pub y_axis: Axis,
+    pub series: Vec<Series>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Axis {
+    pub label: String,
+    pub unit: String,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Series {
+    pub name: String,
+    pub points: Vec<Point>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Point {
+    /// Category label or x value as shown on the chart
+    pub x: String,
+    pub y: Option<f64>,
+}
+
+/// Every chart found in an analysis made with a chart category.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChartExtraction {
+    pub charts: Vec<ChartData>,
+}
+
+impl CategoryExtraction for ChartExtraction {
+    fn handles(category: &ContentCategory) -> bool {
+        matches!(category, ContentCategory::Chart | ContentCategory::Graph | ContentCategory::Dashboard)
+    }
+
+    fn structure(category: &ContentCategory) -> Option<Value> {
+        if !Self::handles(category) {
+            return None;
+        }
+        let axis = json!({
+            "label": "string",
+            "unit": "string",
+            "min": NULLABLE_NUMBER,
+            "max": NULLABLE_NUMBER
+        });
+        Some(
+            json!({
+                "charts": [{
+                    "title": "string",
+                    "chart_type": "string",
+                    "x_axis": axis,
+                    "y_axis": axis,
+                    "series": [{
+                        "name": "string",
+                        "points": [{ "x": "string", "y": NULLABLE_NUMBER }]
+                    }]
+                }]
+            })
+        )
+    }
+
+    fn instructions(category: &ContentCategory) -> Option<&'static str> {
+        Self::handles(category).then_some(
+            "List every chart separately. Read one point per category or tick for each series, in axis order, estimating values from the gridlines when they are not labeled. Write values as plain numbers in the axis unit. For pie charts use the slice labels as x and the percentages as y."
+        )
+    }
+
+    fn from_details(_category: &ContentCategory, details: &Value) -> Result<Self, ProcessorError> {
+        parse_details(details)
+    }
+}
+
+impl ChartData {
+    /// Renders the chart as CSV with one column per series and one row per x value.
+    ///
+    /// Rows follow the order in which x values first appear; missing values are empty.
+    pub fn to_csv(&self) -> String {
+        let mut xs: Vec<&str> = Vec::new();
+        for point in self.series.iter().flat_map(|s| &s.points) {
+            if !xs.contains(&point.x.as_str()) {
+                xs.push(&point.x);
+            }
+        }
+
+        let x_header = if self.x_axis.label.is_empty() { "x" } else { &self.x_axis.label };
+        let mut header = vec![csv_field(x_header)];
+        header.extend(
+            self.series
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    if s.name.is_empty() {
+                        csv_field(&format!("series_{}", i + 1))
+                    } else {
+                        csv_field(&s.name)
+                    }
+                })
+        );
+
+        let mut csv = header.join(",");
+        csv.push('\n');
+        for x in xs {
+            let mut row = vec![csv_field(x)];
+            row.extend(
+                self.series.iter().map(|s| {
+                    s.points
+                        .iter()
+                        .find(|p| p.x == x)
+                        .and_then(|p| p.y)
+                        .map(|y| y.to_string())
+                        .unwrap_or_default()
+                })
+            );
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::analysis_with_details;
+
+    fn point(x: &str, y: Option<f64>) -> Point {
+        Point { x: x.to_string(), y }
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let chart = ChartData {
+            chart_type: "bar".to_string(),
+            x_axis: Axis { label: "Quarter".to_string(), ..Default::default() },
+            series: vec![
+                Series {
+                    name: "Revenue, EUR".to_string(),
+                    points: vec![point("Q1", Some(1.5)), point("Q2", Some(2.0))],
+                },
+                Series {
+                    name: "Costs".to_string(),
+                    points: vec![point("Q2", Some(1.0)), point("Q3", None)],
+                }
+            ],
+            ..Default::default()
+        };
+        assert_eq!(chart.to_csv(), "Quarter,\"Revenue, EUR\",Costs\nQ1,1.5,\nQ2,2,1\nQ3,,\n");
+    }
+
+    #[test]
+    fn test_extraction_from_result() {
+        let result = analysis_with_details(
+            json!({
+                "charts": [{
+                    "chart_type": "line",
+                    "series": [{ "name": "Users", "points": [{ "x": "Jan", "y": 10 }] }]
+                }]
+            })
+        );
+        let extraction = ChartExtraction::from_result(&ContentCategory::Chart, &result)
+            .unwrap()
+            .unwrap();
+        assert_eq!(extraction.charts[0].series[0].points[0].y, Some(10.0));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{ errors::ProcessorError, repair::repair_json, utils::{ csv_field, BoundingBox } };
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+
+/// Differences between a before and an after image, such as two releases of a screen.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Comparison {
+    /// One or two sentences on what changed overall
+    pub summary: String,
+    /// How alike the images are, from 0.0 (unrelated) to 1.0 (identical)
+    pub similarity: f32,
+    /// Most significant differences first
+    pub differences: Vec<Difference>,
+    /// `builtin`, or the `name@version` of the prompt template used
+    #[serde(skip_deserializing)]
+    pub prompt_version: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Difference {
+    pub kind: ChangeKind,
+    /// The element that differs, e.g. "checkout button"
+    pub element: String,
+    /// How it looks in the before image, empty when added
+    pub before: String,
+    /// How it looks in the after image, empty when removed
+    pub after: String,
+    pub significance: Significance,
+    /// Location as fractions of the image size, in the after image unless the element
+    /// was removed
+    pub bbox: Option<BoundingBox>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    #[default]
+    Changed,
+    Moved,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Significance {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+/// Instructions for the comparison mode; the first image is the before image.
+pub const PROMPT: &str =
+    r#"Compare these two images. The first image is the BEFORE version, the second the AFTER version, for example two releases of a user interface, a before/after photo or two product variants.
+
+- List every visible difference once: added and removed elements, changed text, colors, sizes, states or content, and elements that moved.
+- element names the thing that differs; before and after describe it in each image, with text quoted exactly. Leave before empty for added elements and after empty for removed ones.
+- significance is high for changes a user would notice immediately or that change meaning, medium for noticeable ones and low for small visual tweaks.
+- bbox locates the element as fractions of the image width and height between 0 and 1, in the after image, or in the before image for removed elements. Use null when the difference has no single location.
+- Ignore compression artifacts and differences of a pixel or two.
+- similarity is 1.0 for identical images.
+
+Respond with JSON only:
+{
+    "summary": "string",
+    "similarity": 0.0-1.0,
+    "differences": [{
+        "kind": "added|removed|changed|moved",
+        "element": "string",
+        "before": "string",
+        "after": "string",
+        "significance": "low|medium|high",
+        "bbox": { "x": number, "y": number, "width": number, "height": number } | null
+    }]
+}"#;
+
+/// JSON Schema for [`Comparison`], for providers that enforce schemas.
+pub fn schema() -> Value {
+    let number = json!({ "type": "number" });
+    json!({
+        "type": "object",
+        "properties": {
+            "summary": { "type": "string" },
+            "similarity": { "type": "number", "minimum": 0.0, "maximum": 1.0 },
+            "differences": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "kind": { "type": "string", "enum": ["added", "removed", "changed", "moved"] },
+                        "element": { "type": "string" },
+                        "before": { "type": "string" },
+                        "after": { "type": "string" },
+                        "significance": { "type": "string", "enum": ["low", "medium", "high"] },
+                        "bbox": {
+                            "anyOf": [
+                                {
+                                    "type": "object",
+                                    "properties": { "x": number, "y": number, "width": number, "height": number },
+                                    "required": ["x", "y", "width", "height"],
+                                    "additionalProperties": false
+                                },
+                                { "type": "null" }
+                            ]
+                        }
+                    },
+                    "required": ["kind", "element", "before", "after", "significance", "bbox"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["summary", "similarity", "differences"],
+        "additionalProperties": false
+    })
+}
+
+impl Comparison {
+    /// Parses provider output, clamping boxes to the image and ordering the
+    /// differences by significance.
+    pub fn parse(output: &str) -> Result<Self, ProcessorError> {
+        let (json, _) = repair_json(output);
+        let mut comparison: Self = serde_json::from_str(&json).map_err(|e| {
+            ProcessorError::ResponseParseError(
+                format!("Failed to parse comparison: {}. Response text: {}", e, output)
+            )
+        })?;
+
+        comparison.similarity = comparison.similarity.clamp(0.0, 1.0);
+        comparison.differences.retain(|d| !d.element.trim().is_empty());
+        for difference in &mut comparison.differences {
+            difference.bbox = difference.bbox
+                .filter(|b| [b.x, b.y, b.width, b.height].iter().all(|v| v.is_finite()))
+                .map(BoundingBox::clamp)
+                .filter(|b| b.width > 0.0 && b.height > 0.0);
+        }
+        // Stable, so the model's order is kept within each significance
+        comparison.differences.sort_by_key(|d| std::cmp::Reverse(d.significance));
+        Ok(comparison)
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    /// Renders the differences as a Markdown table for review or release notes.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!("{}\n\n", self.summary.trim());
+        if self.is_identical() {
+            markdown.push_str("No differences found.\n");
+            return markdown;
+        }
+        markdown.push_str("| Change | Element | Before | After | Significance |\n");
+        markdown.push_str("| --- | --- | --- | --- | --- |\n");
+        let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+        for d in &self.differences {
+            markdown.push_str(
+                &format!(
+                    "| {} | {} | {} | {} | {} |\n",
+                    d.kind.as_str(),
+                    cell(&d.element),
+                    cell(&d.before),
+                    cell(&d.after),
+                    d.significance.as_str()
+                )
+            );
+        }
+        markdown
+    }
+
+    /// Renders one row per difference, with the box in fractions of the image size.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,element,before,after,significance,x,y,width,height\n");
+        for d in &self.differences {
+            let bbox = d.bbox.map_or(",,,".to_string(), |b| {
+                format!("{},{},{},{}", b.x, b.y, b.width, b.height)
+            });
+            csv.push_str(
+                &format!(
+                    "{},{},{},{},{},{}\n",
+                    d.kind.as_str(),
+                    csv_field(&d.element),
+                    csv_field(&d.before),
+                    csv_field(&d.after),
+                    d.significance.as_str(),
+                    bbox
+                )
+            );
+        }
+        csv
+    }
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+            Self::Moved => "moved",
+        }
+    }
+}
+
+impl Significance {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str =
+        r#"```json
+{
+    "summary": "The checkout button was renamed and a banner was added.",
+    "similarity": 0.9,
+    "differences": [
+        { "kind": "changed", "element": "button color", "before": "blue", "after": "green", "significance": "low", "bbox": null },
+        { "kind": "changed", "element": "checkout button", "before": "\"Buy\"", "after": "\"Buy now\"", "significance": "high", "bbox": { "x": 0.7, "y": 0.8, "width": 0.5, "height": 0.1 } },
+        { "kind": "added", "element": "sale banner", "before": "", "after": "Red | banner", "significance": "medium", "bbox": { "x": 0.1, "y": 0.1, "width": 0.0, "height": 0.2 } }
+    ]
+}
+```"#;
+
+    #[test]
+    fn test_parse_orders_and_clamps() {
+        let comparison = Comparison::parse(OUTPUT).unwrap();
+        let elements: Vec<_> = comparison.differences
+            .iter()
+            .map(|d| d.element.as_str())
+            .collect();
+        assert_eq!(elements, vec!["checkout button", "sale banner", "button color"]);
+        let bbox = comparison.differences[0].bbox.unwrap();
+        assert!((bbox.width - 0.3).abs() < 1e-6);
+        assert_eq!(comparison.differences[1].bbox, None);
+        assert_eq!(comparison.differences[1].kind, ChangeKind::Added);
+        assert!(!comparison.is_identical());
+    }
+
+    #[test]
+    fn test_exports() {
+        let comparison = Comparison::parse(OUTPUT).unwrap();
+        let markdown = comparison.to_markdown();
+        assert!(markdown.contains("| added | sale banner |  | Red \\| banner | medium |"));
+        let csv = comparison.to_csv();
+        assert!(csv.contains("\nchanged,checkout button,\"\"\"Buy\"\"\",\"\"\"Buy now\"\"\",high,0.7,0.8,"));
+        assert!(csv.ends_with("changed,button color,blue,green,low,,,,\n"));
+
+        let identical = Comparison::parse(r#"{"summary": "Same.", "similarity": 1.0}"#).unwrap();
+        assert!(identical.is_identical());
+        assert_eq!(identical.to_markdown(), "Same.\n\nNo differences found.\n");
+    }
+}
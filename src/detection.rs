@@ -0,0 +1,219 @@
+use crate::{ analysis::AnalysisResult, utils::BoundingBox };
+use serde::Serialize;
+use serde_json::{ json, Value };
+
+/// Labeled objects located in one image, for pre-labeling annotation datasets.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Detections {
+    pub file_name: String,
+    pub image_width: u32,
+    pub image_height: u32,
+    pub objects: Vec<DetectedObject>,
+    /// Boxes that had to be clamped or were dropped
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DetectedObject {
+    pub label: String,
+    pub description: String,
+    /// Position as fractions of the image size
+    pub bbox: BoundingBox,
+    /// Position in pixels: left, top, right, bottom
+    pub pixels: [u32; 4],
+}
+
+impl Detections {
+    /// Collects the main elements that have a bounding box, clamping boxes to the
+    /// image and dropping those that cover no pixels.
+    pub fn from_result(
+        result: &AnalysisResult,
+        file_name: &str,
+        image_width: u32,
+        image_height: u32
+    ) -> Self {
+        let mut detections = Self {
+            file_name: file_name.to_string(),
+            image_width,
+            image_height,
+            ..Default::default()
+        };
+
+        for (i, element) in result.content.main_elements.iter().enumerate() {
+            let Some(bbox) = element.bbox else {
+                continue;
+            };
+            let label = element.label
+                .as_deref()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .unwrap_or(&element.element_type)
+                .to_lowercase();
+            let path = format!("content.main_elements[{}]", i);
+
+            let valid = [bbox.x, bbox.y, bbox.width, bbox.height].iter().all(|v| v.is_finite());
+            let clamped = if valid { bbox.clamp() } else { BoundingBox::default() };
+            let pixels = [
+                (clamped.x * (image_width as f32)).round() as u32,
+                (clamped.y * (image_height as f32)).round() as u32,
+                ((clamped.x + clamped.width) * (image_width as f32)).round() as u32,
+                ((clamped.y + clamped.height) * (image_height as f32)).round() as u32,
+            ];
+            if pixels[2] <= pixels[0] || pixels[3] <= pixels[1] {
+                detections.warnings.push(format!("{}: dropped {:?}, the box is empty", path, label));
+                continue;
+            }
+            if clamped != bbox {
+                detections.warnings.push(
+                    format!("{}: clamped the box of {:?} to the image bounds", path, label)
+                );
+            }
+
+            detections.objects.push(DetectedObject {
+                label,
+                description: element.description.clone(),
+                bbox: clamped,
+                pixels,
+            });
+        }
+        detections
+    }
+
+    /// Distinct labels in order of first appearance.
+    pub fn labels(&self) -> Vec<&str> {
+        let mut labels: Vec<&str> = Vec::new();
+        for object in &self.objects {
+            if !labels.contains(&object.label.as_str()) {
+                labels.push(&object.label);
+            }
+        }
+        labels
+    }
+
+    /// Renders a COCO object detection dataset with this single image.
+    ///
+    /// Category ids are assigned from 1 in order of first appearance.
+    pub fn to_coco(&self) -> Value {
+        let labels = self.labels();
+        let annotations: Vec<Value> = self.objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| {
+                let [left, top, right, bottom] = object.pixels;
+                let (width, height) = (right - left, bottom - top);
+                json!({
+                    "id": i + 1,
+                    "image_id": 1,
+                    "category_id": labels.iter().position(|l| *l == object.label).unwrap_or(0) + 1,
+                    "bbox": [left, top, width, height],
+                    "area": width * height,
+                    "iscrowd": 0
+                })
+            })
+            .collect();
+        let categories: Vec<Value> = labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| json!({ "id": i + 1, "name": label, "supercategory": "" }))
+            .collect();
+
+        json!({
+            "images": [{
+                "id": 1,
+                "file_name": self.file_name,
+                "width": self.image_width,
+                "height": self.image_height
+            }],
+            "annotations": annotations,
+            "categories": categories
+        })
+    }
+
+    /// Renders the Pascal VOC annotation as JSON, mirroring the element names of the XML format.
+    pub fn to_voc(&self) -> Value {
+        let objects: Vec<Value> = self.objects
+            .iter()
+            .map(|object| {
+                let [xmin, ymin, xmax, ymax] = object.pixels;
+                json!({
+                    "name": object.label,
+                    "pose": "Unspecified",
+                    "truncated": 0,
+                    "difficult": 0,
+                    "bndbox": { "xmin": xmin, "ymin": ymin, "xmax": xmax, "ymax": ymax }
+                })
+            })
+            .collect();
+
+        json!({
+            "annotation": {
+                "filename": self.file_name,
+                "size": { "width": self.image_width, "height": self.image_height, "depth": 3 },
+                "segmented": 0,
+                "object": objects
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{ Content, Element };
+
+    fn element(label: &str, bbox: Option<BoundingBox>) -> Element {
+        Element {
+            element_type: "object".to_string(),
+            label: Some(label.to_string()),
+            bbox,
+            ..Default::default()
+        }
+    }
+
+    fn result() -> AnalysisResult {
+        let bbox = |x, y, width, height| Some(BoundingBox { x, y, width, height });
+        AnalysisResult {
+            content: Content {
+                main_elements: vec![
+                    element("Dog", bbox(0.1, 0.2, 0.3, 0.4)),
+                    element("cat", bbox(0.8, 0.5, 0.4, 0.2)),
+                    element("dog", bbox(1.2, 0.5, 0.1, 0.1)),
+                    element("sky", None)
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_boxes_are_clamped_and_dropped() {
+        let detections = Detections::from_result(&result(), "pets.jpg", 200, 100);
+        assert_eq!(detections.objects.len(), 2);
+        assert_eq!(detections.objects[0].pixels, [20, 20, 80, 60]);
+        assert_eq!(detections.objects[1].pixels, [160, 50, 200, 70]);
+        assert_eq!(detections.warnings.len(), 2);
+        assert!(detections.warnings[0].contains("clamped"));
+        assert!(detections.warnings[1].contains("dropped"));
+    }
+
+    #[test]
+    fn test_coco_export() {
+        let coco = Detections::from_result(&result(), "pets.jpg", 200, 100).to_coco();
+        assert_eq!(coco["images"][0]["width"], 200);
+        assert_eq!(coco["annotations"][0]["bbox"], json!([20, 20, 60, 40]));
+        assert_eq!(coco["annotations"][0]["area"], 2400);
+        assert_eq!(coco["annotations"][1]["category_id"], 2);
+        assert_eq!(coco["categories"][0]["name"], "dog");
+    }
+
+    #[test]
+    fn test_voc_export() {
+        let voc = Detections::from_result(&result(), "pets.jpg", 200, 100).to_voc();
+        assert_eq!(voc["annotation"]["filename"], "pets.jpg");
+        assert_eq!(
+            voc["annotation"]["object"][1]["bndbox"],
+            json!({ "xmin": 160, "ymin": 50, "xmax": 200, "ymax": 70 })
+        );
+    }
+}
@@ -0,0 +1,398 @@
+use crate::{
+    errors::ProcessorError,
+    extraction::{ parse_details, CategoryExtraction },
+    prompts::{ ContentCategory, BOOLEAN },
+};
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+use std::collections::{ HashMap, HashSet };
+
+/// Nodes and edges read from a flowchart, diagram or mind map.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiagramData {
+    pub title: String,
+    /// Main flow direction: `TB`, `BT`, `LR` or `RL`
+    pub direction: String,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Node {
+    pub id: String,
+    pub label: String,
+    /// `rectangle`, `rounded`, `diamond`, `circle`, `ellipse`, `parallelogram`,
+    /// `cylinder`, `hexagon` or `stadium`
+    pub shape: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub label: String,
+    pub dashed: bool,
+    pub directed: bool,
+}
+
+/// A diagram with the structural problems found in it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiagramExtraction {
+    pub diagram: DiagramData,
+    /// Duplicate node ids and edges pointing at unknown nodes; such edges are left out
+    /// of the Mermaid and Graphviz output
+    pub issues: Vec<String>,
+    #[serde(skip)]
+    mind_map: bool,
+}
+
+impl CategoryExtraction for DiagramExtraction {
+    fn handles(category: &ContentCategory) -> bool {
+        matches!(category, ContentCategory::Flowchart | ContentCategory::Diagram | ContentCategory::MindMap)
+    }
+
+    fn structure(category: &ContentCategory) -> Option<Value> {
+        if !Self::handles(category) {
+            return None;
+        }
+        Some(
+            json!({
+                "title": "string",
+                "direction": "TB|LR",
+                "nodes": [{ "id": "string", "label": "string", "shape": "string" }],
+                "edges": [{
+                    "from": "node id",
+                    "to": "node id",
+                    "label": "string",
+                    "dashed": BOOLEAN,
+                    "directed": BOOLEAN
+                }]
+            })
+        )
+    }
+
+    fn instructions(category: &ContentCategory) -> Option<&'static str> {
+        Self::handles(category).then_some(
+            "Give every box, decision, bubble or shape a short unique id and copy its text exactly as the label. Use shape names rectangle, rounded, diamond, circle, ellipse, parallelogram, cylinder, hexagon or stadium. Add one edge per connector using node ids; set directed when it has an arrowhead. For mind maps, point edges from parent to child."
+        )
+    }
+
+    fn from_details(category: &ContentCategory, details: &Value) -> Result<Self, ProcessorError> {
+        parse_details::<DiagramData>(details).map(|diagram| Self {
+            issues: diagram.issues(),
+            diagram,
+            mind_map: matches!(category, ContentCategory::MindMap),
+        })
+    }
+}
+
+impl DiagramExtraction {
+    /// Mermaid source, using `mindmap` syntax for mind maps with a single root.
+    pub fn to_mermaid(&self) -> String {
+        if self.mind_map {
+            if let Some(mindmap) = self.diagram.to_mermaid_mindmap() {
+                return mindmap;
+            }
+        }
+        self.diagram.to_mermaid()
+    }
+
+    pub fn to_dot(&self) -> String {
+        self.diagram.to_dot()
+    }
+}
+
+impl DiagramData {
+    /// Lists duplicate node ids and edges whose endpoints are not nodes.
+    pub fn issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        let mut seen = HashSet::new();
+        for node in &self.nodes {
+            if !seen.insert(node.id.as_str()) {
+                issues.push(format!("duplicate node id {:?}", node.id));
+            }
+        }
+        for (i, edge) in self.edges.iter().enumerate() {
+            for end in [&edge.from, &edge.to] {
+                if !seen.contains(end.as_str()) {
+                    issues.push(format!("edges[{}] references unknown node {:?}", i, end));
+                }
+            }
+        }
+        issues
+    }
+
+    fn valid_edges(&self) -> impl Iterator<Item = &Edge> {
+        let ids: HashSet<&str> = self.nodes
+            .iter()
+            .map(|n| n.id.as_str())
+            .collect();
+        self.edges.iter().filter(move |e| ids.contains(e.from.as_str()) && ids.contains(e.to.as_str()))
+    }
+
+    fn first_nodes(&self) -> impl Iterator<Item = &Node> {
+        let mut seen = HashSet::new();
+        self.nodes.iter().filter(move |n| seen.insert(n.id.as_str()))
+    }
+
+    fn direction(&self) -> &'static str {
+        match self.direction.trim().to_ascii_uppercase().as_str() {
+            "LR" => "LR",
+            "RL" => "RL",
+            "BT" => "BT",
+            _ => "TB",
+        }
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let ids = mermaid_ids(&self.nodes);
+        let mut lines = vec![format!("flowchart {}", self.direction())];
+
+        for node in self.first_nodes() {
+            let label = mermaid_label(&node.label, &node.id);
+            let (open, close) = match node.shape.to_ascii_lowercase().as_str() {
+                "rounded" => ("(", ")"),
+                "diamond" => ("{", "}"),
+                "circle" | "ellipse" => ("((", "))"),
+                "parallelogram" => ("[/", "/]"),
+                "cylinder" => ("[(", ")]"),
+                "hexagon" => ("{{", "}}"),
+                "stadium" => ("([", "])"),
+                _ => ("[", "]"),
+            };
+            lines.push(format!("    {}{}{}{}", ids[node.id.as_str()], open, label, close));
+        }
+
+        for edge in self.valid_edges() {
+            let arrow = match (edge.dashed, edge.directed) {
+                (false, true) => "-->",
+                (false, false) => "---",
+                (true, true) => "-.->",
+                (true, false) => "-.-",
+            };
+            let label = if edge.label.is_empty() {
+                String::new()
+            } else {
+                format!("|{}|", mermaid_label(&edge.label, ""))
+            };
+            lines.push(
+                format!(
+                    "    {} {}{} {}",
+                    ids[edge.from.as_str()],
+                    arrow,
+                    label,
+                    ids[edge.to.as_str()]
+                )
+            );
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    /// Renders Mermaid `mindmap` syntax, following edges from the single root.
+    /// Returns `None` when the edges don't form a tree with one root.
+    fn to_mermaid_mindmap(&self) -> Option<String> {
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut has_parent = HashSet::new();
+        for edge in self.valid_edges() {
+            children.entry(edge.from.as_str()).or_default().push(edge.to.as_str());
+            if !has_parent.insert(edge.to.as_str()) {
+                return None;
+            }
+        }
+        let labels: HashMap<&str, &str> = self
+            .first_nodes()
+            .map(|n| (n.id.as_str(), n.label.as_str()))
+            .collect();
+        let mut roots = self.first_nodes().filter(|n| !has_parent.contains(n.id.as_str()));
+        let root = roots.next()?;
+        if roots.next().is_some() {
+            return None;
+        }
+
+        let text = |id: &str| {
+            let label = labels.get(id).copied().filter(|l| !l.is_empty()).unwrap_or(id);
+            label.replace(['(', ')', '[', ']', '{', '}', '\n'], " ").trim().to_string()
+        };
+        let mut lines = vec!["mindmap".to_string(), format!("  root(({}))", text(&root.id))];
+        let mut stack: Vec<(&str, usize)> = children
+            .get(root.id.as_str())
+            .map(|c| c.iter().rev().map(|id| (*id, 2)).collect())
+            .unwrap_or_default();
+        let mut visited = HashSet::from([root.id.as_str()]);
+        while let Some((id, depth)) = stack.pop() {
+            if !visited.insert(id) {
+                return None;
+            }
+            lines.push(format!("{}{}", "  ".repeat(depth), text(id)));
+            if let Some(c) = children.get(id) {
+                stack.extend(c.iter().rev().map(|child| (*child, depth + 1)));
+            }
+        }
+        // Nodes unreachable from the root can't be placed in the tree
+        if visited.len() != labels.len() {
+            return None;
+        }
+        Some(lines.join("\n") + "\n")
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec![
+            "digraph diagram {".to_string(),
+            format!("    rankdir={};", self.direction())
+        ];
+        if !self.title.is_empty() {
+            lines.push(format!("    label={};", dot_string(&self.title)));
+            lines.push("    labelloc=t;".to_string());
+        }
+        lines.push("    node [shape=box];".to_string());
+
+        for node in self.first_nodes() {
+            let shape = match node.shape.to_ascii_lowercase().as_str() {
+                "rounded" | "stadium" => "box, style=rounded",
+                "diamond" => "diamond",
+                "circle" => "circle",
+                "ellipse" => "ellipse",
+                "parallelogram" => "parallelogram",
+                "cylinder" => "cylinder",
+                "hexagon" => "hexagon",
+                _ => "box",
+            };
+            let label = if node.label.is_empty() { &node.id } else { &node.label };
+            lines.push(
+                format!("    {} [label={}, shape={}];", dot_string(&node.id), dot_string(label), shape)
+            );
+        }
+
+        for edge in self.valid_edges() {
+            let mut attributes = Vec::new();
+            if !edge.label.is_empty() {
+                attributes.push(format!("label={}", dot_string(&edge.label)));
+            }
+            if edge.dashed {
+                attributes.push("style=dashed".to_string());
+            }
+            if !edge.directed {
+                attributes.push("dir=none".to_string());
+            }
+            let attributes = if attributes.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attributes.join(", "))
+            };
+            lines.push(
+                format!("    {} -> {}{};", dot_string(&edge.from), dot_string(&edge.to), attributes)
+            );
+        }
+
+        lines.push("}".to_string());
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Maps node ids onto unique Mermaid-safe identifiers.
+fn mermaid_ids(nodes: &[Node]) -> HashMap<&str, String> {
+    let mut ids = HashMap::new();
+    let mut used = HashSet::new();
+    for node in nodes {
+        if ids.contains_key(node.id.as_str()) {
+            continue;
+        }
+        let mut id: String = node.id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        // `end` closes subgraphs and ids must not start with a digit
+        if id.is_empty() || id.starts_with(|c: char| c.is_ascii_digit()) || id.eq_ignore_ascii_case("end") {
+            id = format!("n_{}", id);
+        }
+        let mut unique = id.clone();
+        let mut suffix = 2;
+        while !used.insert(unique.clone()) {
+            unique = format!("{}_{}", id, suffix);
+            suffix += 1;
+        }
+        ids.insert(node.id.as_str(), unique);
+    }
+    ids
+}
+
+fn mermaid_label(label: &str, fallback: &str) -> String {
+    let label = if label.is_empty() { fallback } else { label };
+    format!("\"{}\"", label.replace('"', "#quot;").replace('\n', "<br>"))
+}
+
+fn dot_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, label: &str, shape: &str) -> Node {
+        Node { id: id.to_string(), label: label.to_string(), shape: shape.to_string() }
+    }
+
+    fn edge(from: &str, to: &str, label: &str) -> Edge {
+        Edge {
+            from: from.to_string(),
+            to: to.to_string(),
+            label: label.to_string(),
+            dashed: false,
+            directed: true,
+        }
+    }
+
+    fn login_flow() -> DiagramData {
+        DiagramData {
+            title: "Login".to_string(),
+            direction: "LR".to_string(),
+            nodes: vec![
+                node("start", "Open app", "rounded"),
+                node("check", "Signed in?", "diamond"),
+                node("end", "Home \"feed\"", "rectangle")
+            ],
+            edges: vec![edge("start", "check", ""), edge("check", "end", "yes"), edge("check", "login", "no")],
+        }
+    }
+
+    #[test]
+    fn test_issues() {
+        assert_eq!(login_flow().issues(), vec!["edges[2] references unknown node \"login\""]);
+    }
+
+    #[test]
+    fn test_mermaid_export() {
+        assert_eq!(
+            login_flow().to_mermaid(),
+            "flowchart LR\n    start(\"Open app\")\n    check{\"Signed in?\"}\n    n_end[\"Home #quot;feed#quot;\"]\n    start --> check\n    check -->|\"yes\"| n_end\n"
+        );
+    }
+
+    #[test]
+    fn test_dot_export() {
+        let dot = login_flow().to_dot();
+        assert!(dot.starts_with("digraph diagram {\n    rankdir=LR;"));
+        assert!(dot.contains("    \"end\" [label=\"Home \\\"feed\\\"\", shape=box];"));
+        assert!(dot.contains("    \"check\" -> \"end\" [label=\"yes\"];"));
+        assert!(!dot.contains("login"));
+    }
+
+    #[test]
+    fn test_mindmap_export() {
+        let extraction = DiagramExtraction {
+            diagram: DiagramData {
+                nodes: vec![node("r", "Product", ""), node("a", "Pricing", ""), node("b", "Tiers (new)", "")],
+                edges: vec![edge("r", "a", ""), edge("a", "b", "")],
+                ..Default::default()
+            },
+            issues: Vec::new(),
+            mind_map: true,
+        };
+        assert_eq!(extraction.to_mermaid(), "mindmap\n  root((Product))\n    Pricing\n      Tiers  new\n");
+    }
+}
@@ -0,0 +1,418 @@
+use crate::{
+    errors::ProcessorError,
+    extraction::{ parse_details, CategoryExtraction },
+    prompts::{ ContentCategory, BOOLEAN, NULLABLE_NUMBER },
+};
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+
+/// Largest difference between two amounts that is still treated as rounding.
+const AMOUNT_TOLERANCE: f64 = 0.011;
+
+/// Fields extracted from receipts and invoices.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReceiptDetails {
+    pub merchant: String,
+    pub merchant_address: String,
+    /// Invoice or receipt number
+    pub document_number: String,
+    /// Issue date as `YYYY-MM-DD`
+    pub date: String,
+    /// Due date as `YYYY-MM-DD`, for invoices
+    pub due_date: String,
+    /// ISO 4217 code such as `USD`
+    pub currency: String,
+    pub line_items: Vec<LineItem>,
+    pub subtotal: Option<f64>,
+    pub taxes: Vec<Tax>,
+    pub tip: Option<f64>,
+    pub total: Option<f64>,
+    pub payment_method: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LineItem {
+    pub description: String,
+    pub quantity: Option<f64>,
+    pub unit_price: Option<f64>,
+    pub amount: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tax {
+    pub name: String,
+    /// Rate in percent, e.g. `8.5`
+    pub rate: Option<f64>,
+    pub amount: Option<f64>,
+    /// Whether the amount is already contained in the prices, as for VAT marked "incl."
+    pub included: bool,
+}
+
+/// Contact fields from a business card.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BusinessCardDetails {
+    pub name: String,
+    pub title: String,
+    pub organization: String,
+    pub emails: Vec<String>,
+    pub phones: Vec<Phone>,
+    pub websites: Vec<String>,
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Phone {
+    /// `mobile`, `work`, `fax`, ...
+    pub kind: String,
+    pub number: String,
+}
+
+/// Key/value pairs from a filled-in form.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormDetails {
+    pub title: String,
+    pub fields: Vec<FormField>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormField {
+    pub label: String,
+    pub value: String,
+    /// `text`, `checkbox`, `date`, `signature`, ...
+    pub field_type: String,
+}
+
+/// Printed fields from an identity document.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdentificationDetails {
+    /// `passport`, `driver_license`, `national_id`, ...
+    pub document_type: String,
+    pub issuing_authority: String,
+    pub country: String,
+    pub document_number: String,
+    pub full_name: String,
+    pub date_of_birth: String,
+    pub issue_date: String,
+    pub expiry_date: String,
+    pub other_fields: Vec<FormField>,
+}
+
+/// Typed `category_details` for the document categories.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "category", rename_all = "snake_case")]
+pub enum DocumentDetails {
+    Receipt(ReceiptDetails),
+    Invoice(ReceiptDetails),
+    BusinessCard(BusinessCardDetails),
+    Form(FormDetails),
+    Identification(IdentificationDetails),
+}
+
+/// A document with the arithmetic inconsistencies found in it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocumentExtraction {
+    pub details: DocumentDetails,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// An amount that doesn't add up.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Discrepancy {
+    pub field: String,
+    pub expected: f64,
+    pub actual: f64,
+    pub message: String,
+}
+
+impl CategoryExtraction for DocumentExtraction {
+    fn handles(category: &ContentCategory) -> bool {
+        use ContentCategory::*;
+        matches!(category, Receipt | Invoice | BusinessCard | Form | Identification)
+    }
+
+    fn structure(category: &ContentCategory) -> Option<Value> {
+        use ContentCategory::*;
+
+        let structure = match category {
+            Receipt | Invoice =>
+                json!({
+                    "merchant": "string",
+                    "merchant_address": "string",
+                    "document_number": "string",
+                    "date": "YYYY-MM-DD",
+                    "due_date": "YYYY-MM-DD",
+                    "currency": "ISO 4217 code",
+                    "line_items": [{
+                        "description": "string",
+                        "quantity": NULLABLE_NUMBER,
+                        "unit_price": NULLABLE_NUMBER,
+                        "amount": NULLABLE_NUMBER
+                    }],
+                    "subtotal": NULLABLE_NUMBER,
+                    "taxes": [{
+                        "name": "string",
+                        "rate": NULLABLE_NUMBER,
+                        "amount": NULLABLE_NUMBER,
+                        "included": BOOLEAN
+                    }],
+                    "tip": NULLABLE_NUMBER,
+                    "total": NULLABLE_NUMBER,
+                    "payment_method": "string"
+                }),
+            BusinessCard =>
+                json!({
+                    "name": "string",
+                    "title": "string",
+                    "organization": "string",
+                    "emails": ["string"],
+                    "phones": [{ "kind": "string", "number": "string" }],
+                    "websites": ["string"],
+                    "address": "string"
+                }),
+            Form =>
+                json!({
+                    "title": "string",
+                    "fields": [{ "label": "string", "value": "string", "field_type": "string" }]
+                }),
+            Identification =>
+                json!({
+                    "document_type": "string",
+                    "issuing_authority": "string",
+                    "country": "string",
+                    "document_number": "string",
+                    "full_name": "string",
+                    "date_of_birth": "YYYY-MM-DD",
+                    "issue_date": "YYYY-MM-DD",
+                    "expiry_date": "YYYY-MM-DD",
+                    "other_fields": [{ "label": "string", "value": "string", "field_type": "string" }]
+                }),
+            _ => {
+                return None;
+            }
+        };
+        Some(structure)
+    }
+
+    fn instructions(category: &ContentCategory) -> Option<&'static str> {
+        use ContentCategory::*;
+
+        match category {
+            Receipt | Invoice =>
+                Some(
+                    "Write amounts as plain numbers without currency symbols or thousands separators, dates as YYYY-MM-DD and the currency as an ISO 4217 code. Copy amounts exactly as printed, even if they do not add up. Use null for amounts that are not printed. Set included to true only for taxes the receipt marks as already contained in the prices, such as \"incl. VAT\"."
+                ),
+            BusinessCard =>
+                Some("Write phone numbers as printed and list every email address and website."),
+            Form =>
+                Some(
+                    "List every field in reading order, including empty ones with an empty value. For checkboxes use \"checked\" or \"unchecked\" as the value."
+                ),
+            Identification =>
+                Some("Write dates as YYYY-MM-DD and copy document numbers exactly as printed."),
+            _ => None,
+        }
+    }
+
+    fn from_details(category: &ContentCategory, details: &Value) -> Result<Self, ProcessorError> {
+        DocumentDetails::parse(category, details).map(|details| Self {
+            discrepancies: details.discrepancies(),
+            details,
+        })
+    }
+}
+
+impl DocumentDetails {
+    /// Parses `category_details` for a document category.
+    pub fn parse(category: &ContentCategory, details: &Value) -> Result<Self, ProcessorError> {
+        use ContentCategory::*;
+
+        Ok(match category {
+            Receipt => Self::Receipt(parse_details(details)?),
+            Invoice => Self::Invoice(parse_details(details)?),
+            BusinessCard => Self::BusinessCard(parse_details(details)?),
+            Form => Self::Form(parse_details(details)?),
+            Identification => Self::Identification(parse_details(details)?),
+            other => {
+                return Err(
+                    ProcessorError::SchemaViolation(
+                        vec![format!("{} is not a document category", other.label())]
+                    )
+                );
+            }
+        })
+    }
+
+    /// Checks that the amounts add up, for receipts and invoices.
+    pub fn discrepancies(&self) -> Vec<Discrepancy> {
+        match self {
+            Self::Receipt(details) | Self::Invoice(details) => details.discrepancies(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl ReceiptDetails {
+    /// Compares line items, subtotal, taxes, tip and total with each other.
+    pub fn discrepancies(&self) -> Vec<Discrepancy> {
+        let mut discrepancies = Vec::new();
+        let mut check = |field: String, expected: f64, actual: f64, message: &str| {
+            if (expected - actual).abs() > AMOUNT_TOLERANCE {
+                discrepancies.push(Discrepancy {
+                    field,
+                    expected: round_cents(expected),
+                    actual,
+                    message: message.to_string(),
+                });
+            }
+        };
+
+        for (i, item) in self.line_items.iter().enumerate() {
+            if let (Some(quantity), Some(unit_price), Some(amount)) = (
+                item.quantity,
+                item.unit_price,
+                item.amount,
+            ) {
+                check(
+                    format!("line_items[{}].amount", i),
+                    quantity * unit_price,
+                    amount,
+                    "quantity times unit price does not match the line amount"
+                );
+            }
+        }
+
+        let item_amounts: Option<Vec<f64>> = self.line_items
+            .iter()
+            .map(|item| item.amount)
+            .collect();
+        let items_sum = item_amounts
+            .filter(|amounts| !amounts.is_empty())
+            .map(|amounts| amounts.iter().sum::<f64>());
+        // Taxes marked as included are already part of the subtotal
+        let tax_sum: f64 = self.taxes
+            .iter()
+            .filter(|tax| !tax.included)
+            .filter_map(|tax| tax.amount)
+            .sum();
+
+        if let (Some(items_sum), Some(subtotal)) = (items_sum, self.subtotal) {
+            check(
+                "subtotal".to_string(),
+                items_sum,
+                subtotal,
+                "line items do not sum to the subtotal"
+            );
+        }
+
+        // Without a printed subtotal the line items stand in for it
+        if let (Some(base), Some(total)) = (self.subtotal.or(items_sum), self.total) {
+            check(
+                "total".to_string(),
+                base + tax_sum + self.tip.unwrap_or(0.0),
+                total,
+                "subtotal plus taxes and tip does not equal the total"
+            );
+        }
+
+        discrepancies
+    }
+}
+
+fn round_cents(amount: f64) -> f64 {
+    (amount * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::analysis_with_details;
+
+    fn receipt(items: &[f64], subtotal: f64, tax: f64, total: f64) -> ReceiptDetails {
+        ReceiptDetails {
+            line_items: items
+                .iter()
+                .map(|amount| LineItem {
+                    amount: Some(*amount),
+                    ..Default::default()
+                })
+                .collect(),
+            subtotal: Some(subtotal),
+            taxes: vec![Tax {
+                name: "VAT".to_string(),
+                rate: None,
+                amount: Some(tax),
+                included: false,
+            }],
+            total: Some(total),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_consistent_receipt() {
+        assert!(receipt(&[4.5, 3.2], 7.7, 0.62, 8.32).discrepancies().is_empty());
+    }
+
+    #[test]
+    fn test_tax_inclusive_receipt() {
+        let mut details = receipt(&[4.5, 3.2], 7.7, 1.23, 7.7);
+        let discrepancies = details.discrepancies();
+        assert_eq!(discrepancies.len(), 1);
+        assert_eq!(discrepancies[0].expected, 8.93);
+
+        details.taxes[0].included = true;
+        assert!(details.discrepancies().is_empty());
+    }
+
+    #[test]
+    fn test_receipt_discrepancies() {
+        let discrepancies = receipt(&[4.5, 3.2], 7.9, 0.62, 9.0).discrepancies();
+        assert_eq!(discrepancies.len(), 2);
+        assert_eq!(discrepancies[0].field, "subtotal");
+        assert_eq!(discrepancies[0].expected, 7.7);
+        assert_eq!(discrepancies[1].field, "total");
+        assert_eq!(discrepancies[1].expected, 8.52);
+    }
+
+    #[test]
+    fn test_line_item_arithmetic() {
+        let details = ReceiptDetails {
+            line_items: vec![LineItem {
+                description: "Coffee".to_string(),
+                quantity: Some(3.0),
+                unit_price: Some(2.5),
+                amount: Some(7.0),
+            }],
+            ..Default::default()
+        };
+        let discrepancies = details.discrepancies();
+        assert_eq!(discrepancies.len(), 1);
+        assert_eq!(discrepancies[0].field, "line_items[0].amount");
+    }
+
+    #[test]
+    fn test_extraction_from_result() {
+        let result = analysis_with_details(
+            json!({
+                "name": "Ada Lovelace",
+                "emails": ["ada@example.com"],
+                "phones": [{ "kind": "work", "number": "+44 20 7946 0000" }]
+            })
+        );
+        let extraction = DocumentExtraction::from_result(&ContentCategory::BusinessCard, &result)
+            .unwrap()
+            .unwrap();
+        match extraction.details {
+            DocumentDetails::BusinessCard(card) => assert_eq!(card.emails, vec!["ada@example.com"]),
+            other => panic!("expected business card, got {:?}", other),
+        }
+    }
+}
@@ -17,4 +17,18 @@ pub enum ProcessorError {
     #[error("Thumbnail generation failed: {0}")] ThumbnailError(String),
 
     #[error("Image processing error: {0}")] ImageError(String),
+
+    #[error("Invalid region: {0}")] InvalidRegion(String),
+
+    #[error("Export failed: {0}")] ExportError(String),
+
+    #[error("Prompt template error: {0}")] TemplateError(String),
+
+    #[error("Invalid question: {0}")] InvalidQuestion(String),
+
+    #[error("Invalid configuration: {0}")] ConfigError(String),
+
+    #[error("Failed to fetch image: {0}")] FetchError(String),
+
+    #[error("Analysis does not match the schema: {}", .0.join("; "))] SchemaViolation(Vec<String>),
 }
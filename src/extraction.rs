@@ -0,0 +1,95 @@
+use crate::{
+    analysis::AnalysisResult,
+    charts::ChartExtraction,
+    diagrams::DiagramExtraction,
+    documents::DocumentExtraction,
+    errors::ProcessorError,
+    interface::InterfaceDetails,
+    prompts::ContentCategory,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Typed form of `category_details` for a group of categories, such as charts.
+pub trait CategoryExtraction: Sized {
+    /// Whether the category has this structure.
+    fn handles(category: &ContentCategory) -> bool;
+
+    /// Structure requested under `category_details`, `None` for categories not handled.
+    fn structure(category: &ContentCategory) -> Option<Value>;
+
+    /// Reading rules added to the prompt, `None` for categories not handled.
+    fn instructions(category: &ContentCategory) -> Option<&'static str>;
+
+    /// Parses `category_details` of a handled category.
+    fn from_details(category: &ContentCategory, details: &Value) -> Result<Self, ProcessorError>;
+
+    /// Extracts the typed details from an analysis.
+    ///
+    /// Returns `None` for categories without this structure or when the model left
+    /// `category_details` out.
+    fn from_result(
+        category: &ContentCategory,
+        result: &AnalysisResult
+    ) -> Option<Result<Self, ProcessorError>> {
+        if !Self::handles(category) {
+            return None;
+        }
+        let details = result.category_details.as_ref()?;
+        Some(Self::from_details(category, details))
+    }
+}
+
+/// Deserializes `category_details`, reporting a mismatch as a schema violation.
+pub fn parse_details<T: DeserializeOwned>(details: &Value) -> Result<T, ProcessorError> {
+    serde_json
+        ::from_value(details.clone())
+        .map_err(|e| ProcessorError::SchemaViolation(vec![format!("category_details: {}", e)]))
+}
+
+/// Structure requested under `category_details` when a typed extraction handles the category.
+pub fn structure(category: &ContentCategory) -> Option<Value> {
+    DocumentExtraction::structure(category)
+        .or_else(|| ChartExtraction::structure(category))
+        .or_else(|| DiagramExtraction::structure(category))
+        .or_else(|| InterfaceDetails::structure(category))
+}
+
+/// Reading rules for `category_details` when a typed extraction handles the category.
+pub fn instructions(category: &ContentCategory) -> Option<&'static str> {
+    DocumentExtraction::instructions(category)
+        .or_else(|| ChartExtraction::instructions(category))
+        .or_else(|| DiagramExtraction::instructions(category))
+        .or_else(|| InterfaceDetails::instructions(category))
+}
+
+/// An analysis holding only `category_details`, for the extraction tests.
+#[cfg(test)]
+pub(crate) fn analysis_with_details(details: Value) -> AnalysisResult {
+    AnalysisResult {
+        category_details: Some(details),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_result_checks_category_and_details() {
+        let result = analysis_with_details(json!({ "charts": "not a list" }));
+        assert!(ChartExtraction::from_result(&ContentCategory::Photo, &result).is_none());
+        assert!(ChartExtraction::from_result(&ContentCategory::Chart, &AnalysisResult::default()).is_none());
+        match ChartExtraction::from_result(&ContentCategory::Chart, &result) {
+            Some(Err(ProcessorError::SchemaViolation(violations))) =>
+                assert!(violations[0].starts_with("category_details: ")),
+            other => panic!("expected a schema violation, got {:?}", other),
+        }
+
+        assert!(structure(&ContentCategory::Invoice).is_some());
+        assert!(instructions(&ContentCategory::MindMap).is_some());
+        assert!(structure(&ContentCategory::Recipe).is_none());
+    }
+}
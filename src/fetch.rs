@@ -0,0 +1,481 @@
+use crate::errors::ProcessorError;
+use reqwest::{ header, redirect::Policy, Client, Url };
+use std::{ net::{ IpAddr, Ipv4Addr, SocketAddr }, str::FromStr, time::Duration };
+use tracing::warn;
+
+/// Largest image downloaded, in bytes.
+pub const DEFAULT_MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// Time allowed for a download, including redirects.
+pub const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Redirects followed before giving up.
+pub const DEFAULT_MAX_REDIRECTS: usize = 3;
+
+/// Images one request may fetch by URL, well below the number of uploads it may send.
+pub const DEFAULT_MAX_FETCHES: usize = 10;
+
+/// Bytes one request may fetch by URL in total.
+pub const DEFAULT_MAX_FETCHED_BYTES: usize = 50 * 1024 * 1024;
+
+/// Fetches and bytes left to one request, shared by all of its URLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchBudget {
+    fetches: usize,
+    bytes: usize,
+}
+
+/// A network or host name that may be fetched from even though it is not public.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AllowedHost {
+    /// An address range such as `10.0.0.0/8`, or a single address
+    Network {
+        addr: IpAddr,
+        prefix: u8,
+    },
+    /// A host name such as `cms.internal`, allowed whatever it resolves to
+    Name(String),
+}
+
+impl AllowedHost {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self, ip) {
+            (Self::Network { addr: IpAddr::V4(network), prefix }, IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(*prefix)).unwrap_or(0);
+                (u32::from(*network) & mask) == (u32::from(ip) & mask)
+            }
+            (Self::Network { addr: IpAddr::V6(network), prefix }, IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(*prefix)).unwrap_or(0);
+                (u128::from(*network) & mask) == (u128::from(ip) & mask)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for AllowedHost {
+    type Err = ProcessorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || ProcessorError::FetchError(format!("Invalid allowlist entry {:?}", s));
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        match addr.parse::<IpAddr>() {
+            Ok(addr) => {
+                let max = if addr.is_ipv4() { 32 } else { 128 };
+                let prefix = prefix.unwrap_or(max);
+                if prefix > max {
+                    return Err(invalid());
+                }
+                Ok(Self::Network { addr, prefix })
+            }
+            Err(_) if prefix.is_none() && !s.is_empty() && !s.contains([':', ' ']) =>
+                Ok(Self::Name(s.to_lowercase())),
+            Err(_) => Err(invalid()),
+        }
+    }
+}
+
+/// Whether an address is on the public internet, rather than loopback, private,
+/// link-local, shared, benchmarking, multicast or reserved. IPv6 addresses embedding an
+/// IPv4 address (mapped, compatible, NAT64 and 6to4) are judged by that address.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(
+                ip.is_private() ||
+                ip.is_loopback() ||
+                ip.is_link_local() ||
+                ip.is_unspecified() ||
+                ip.is_broadcast() ||
+                ip.is_multicast() ||
+                ip.is_documentation() ||
+                a == 0 ||
+                (a == 100 && (b & 0xc0) == 64) ||
+                (a == 192 && b == 0 && c == 0) ||
+                (a == 198 && (b & 0xfe) == 18) ||
+                a >= 240
+            )
+        }
+        IpAddr::V6(ip) => {
+            if ip.is_loopback() || ip.is_unspecified() {
+                return false;
+            }
+            if let Some(embedded) = embedded_ipv4(ip.segments()) {
+                return is_public(IpAddr::V4(embedded));
+            }
+            let first = ip.segments()[0];
+            !(
+                ip.is_multicast() ||
+                (first & 0xfe00) == 0xfc00 ||
+                (first & 0xffc0) == 0xfe80
+            )
+        }
+    }
+}
+
+// The IPv4 address in ::ffff:a.b.c.d, ::a.b.c.d, 64:ff9b::a.b.c.d and 2002:aabb:ccdd::/48
+fn embedded_ipv4(segments: [u16; 8]) -> Option<Ipv4Addr> {
+    let v4 = |high: u16, low: u16| Some(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)));
+    match segments {
+        [0, 0, 0, 0, 0, 0xffff | 0, high, low] => v4(high, low),
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => v4(high, low),
+        [0x2002, high, low, ..] => v4(high, low),
+        _ => None,
+    }
+}
+
+/// The address of a URL host given as an IP address, such as `127.0.0.1` or `[::1]`.
+fn ip_literal(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// Downloads images by URL, refusing addresses that are not public unless allowlisted.
+///
+/// Host names are resolved once per request and connections are pinned to the checked
+/// address, so a name cannot resolve to a public address for the check and a private
+/// one for the download. Every redirect is checked the same way.
+#[derive(Debug, Clone)]
+pub struct ImageFetcher {
+    max_bytes: usize,
+    timeout: Duration,
+    max_redirects: usize,
+    allowlist: Vec<AllowedHost>,
+    budget: FetchBudget,
+}
+
+impl Default for ImageFetcher {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_IMAGE_BYTES,
+            timeout: DEFAULT_FETCH_TIMEOUT,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            allowlist: Vec::new(),
+            budget: FetchBudget { fetches: DEFAULT_MAX_FETCHES, bytes: DEFAULT_MAX_FETCHED_BYTES },
+        }
+    }
+}
+
+impl ImageFetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Allows fetching from these networks and hosts even though they are not public.
+    pub fn with_allowlist(mut self, allowlist: Vec<AllowedHost>) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    /// Limits the images and total bytes one request may fetch.
+    pub fn with_request_limits(mut self, fetches: usize, bytes: usize) -> Self {
+        self.budget = FetchBudget { fetches, bytes };
+        self
+    }
+
+    /// A full budget for one request, to pass to [`ImageFetcher::fetch_within`] for
+    /// each of its URLs.
+    pub fn budget(&self) -> FetchBudget {
+        self.budget
+    }
+
+    /// Downloads an image served with an `image/*` content type.
+    pub async fn fetch(&self, url: &str) -> Result<Vec<u8>, ProcessorError> {
+        self.fetch_within(url, &mut self.budget()).await
+    }
+
+    /// Downloads an image like [`ImageFetcher::fetch`], counting it against the budget
+    /// of the request.
+    pub async fn fetch_within(
+        &self,
+        url: &str,
+        budget: &mut FetchBudget
+    ) -> Result<Vec<u8>, ProcessorError> {
+        if budget.fetches == 0 {
+            return Err(
+                ProcessorError::FetchError(
+                    format!("At most {} images can be fetched per request", self.budget.fetches)
+                )
+            );
+        }
+        budget.fetches -= 1;
+        let url = Url::parse(url.trim()).map_err(|e| {
+            ProcessorError::FetchError(format!("Invalid URL {:?}: {}", url, e))
+        })?;
+        let max_bytes = self.max_bytes.min(budget.bytes);
+        let data = tokio::time
+            ::timeout(self.timeout, self.follow(url, max_bytes)).await
+            .map_err(|_| {
+                ProcessorError::FetchError(
+                    format!("Timed out after {} seconds", self.timeout.as_secs_f32())
+                )
+            })??;
+        budget.bytes -= data.len();
+        Ok(data)
+    }
+
+    async fn follow(&self, mut url: Url, max_bytes: usize) -> Result<Vec<u8>, ProcessorError> {
+        for _ in 0..=self.max_redirects {
+            let addr = self.resolve(&url).await?;
+            // A proxy would connect on its own, so none is used
+            let mut client = Client::builder()
+                .redirect(Policy::none())
+                .no_proxy()
+                .connect_timeout(self.timeout);
+            if let Some(domain) = url.host_str().filter(|host| ip_literal(host).is_none()) {
+                client = client.resolve(domain, addr);
+            }
+            let response = client
+                .build()?
+                .get(url.clone())
+                .header(header::ACCEPT, "image/*")
+                .send().await?;
+
+            let status = response.status();
+            if status.is_redirection() {
+                let location = response.headers()
+                    .get(header::LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .ok_or_else(|| {
+                        ProcessorError::FetchError(format!("Redirect without a location from {}", url))
+                    })?;
+                url = url.join(location).map_err(|e| {
+                    ProcessorError::FetchError(format!("Invalid redirect to {:?}: {}", location, e))
+                })?;
+                continue;
+            }
+            if !status.is_success() {
+                return Err(ProcessorError::FetchError(format!("{} returned status {}", url, status)));
+            }
+            return self.read(response, max_bytes).await;
+        }
+        Err(ProcessorError::FetchError(format!("More than {} redirects", self.max_redirects)))
+    }
+
+    /// Resolves the host of the URL to an address it is safe to connect to.
+    async fn resolve(&self, url: &Url) -> Result<SocketAddr, ProcessorError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(
+                ProcessorError::FetchError(
+                    format!("Unsupported scheme {:?}, use http or https", url.scheme())
+                )
+            );
+        }
+        let port = url.port_or_known_default().unwrap_or(80);
+        let host = url
+            .host_str()
+            .ok_or_else(|| ProcessorError::FetchError(format!("URL {} has no host", url)))?;
+        // Failed lookups and private addresses get the same answer, so clients cannot
+        // probe which internal names exist
+        let refused = || {
+            ProcessorError::FetchError(format!("{} is not a reachable public host", host))
+        };
+        let (name, addrs) = match ip_literal(host) {
+            Some(ip) => (None, vec![SocketAddr::new(ip, port)]),
+            None => {
+                let addrs: Vec<SocketAddr> = tokio::net
+                    ::lookup_host((host, port)).await
+                    .map_err(|e| {
+                        warn!("Refused to fetch {}: failed to resolve {}: {}", url, host, e);
+                        refused()
+                    })?
+                    .collect();
+                (Some(host.to_lowercase()), addrs)
+            }
+        };
+
+        let name_allowed = name.is_some_and(|name| self.allowlist.contains(&AllowedHost::Name(name)));
+        let blocked = addrs.iter().find(|addr| {
+            !name_allowed &&
+                !is_public(addr.ip()) &&
+                !self.allowlist.iter().any(|allowed| allowed.contains(addr.ip()))
+        });
+        if let Some(addr) = blocked {
+            warn!("Refused to fetch {}: {} resolves to {}", url, host, addr.ip());
+            return Err(refused());
+        }
+        addrs.first().copied().ok_or_else(|| {
+            warn!("Refused to fetch {}: {} has no addresses", url, host);
+            refused()
+        })
+    }
+
+    async fn read(
+        &self,
+        mut response: reqwest::Response,
+        max_bytes: usize
+    ) -> Result<Vec<u8>, ProcessorError> {
+        let content_type = response.headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        let media_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+        if !media_type.starts_with("image/") {
+            return Err(
+                ProcessorError::FetchError(
+                    format!("Expected an image, got content type {:?}", content_type)
+                )
+            );
+        }
+        let too_large = || {
+            ProcessorError::FetchError(
+                if max_bytes < self.max_bytes {
+                    format!("Images fetched for one request can total at most {} bytes", self.budget.bytes)
+                } else {
+                    format!("Image is larger than {} bytes", self.max_bytes)
+                }
+            )
+        };
+        if response.content_length().is_some_and(|length| length > (max_bytes as u64)) {
+            return Err(too_large());
+        }
+
+        // The declared length may be missing or wrong, so the limit is enforced while reading
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if data.len() + chunk.len() > max_bytes {
+                return Err(too_large());
+            }
+            data.extend_from_slice(&chunk);
+        }
+        if data.is_empty() {
+            return Err(ProcessorError::FetchError("Received an empty image".to_string()));
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{ io::{ AsyncReadExt, AsyncWriteExt }, net::TcpListener };
+
+    /// Serves canned responses by path on a local port.
+    async fn serve() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    break;
+                };
+                tokio::spawn(async move {
+                    let mut request = vec![0; 4096];
+                    let read = stream.read(&mut request).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&request[..read]).to_string();
+                    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    let response = match path.as_str() {
+                        "/photo.png" => "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 5\r\n\r\npixel".to_string(),
+                        "/page" => "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 4\r\n\r\nhtml".to_string(),
+                        "/large.png" => format!("HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 64\r\n\r\n{}", "x".repeat(64)),
+                        "/redirect" => "HTTP/1.1 302 Found\r\nLocation: /photo.png\r\nContent-Length: 0\r\n\r\n".to_string(),
+                        "/loop" => "HTTP/1.1 302 Found\r\nLocation: /loop\r\nContent-Length: 0\r\n\r\n".to_string(),
+                        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+                    };
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_private_addresses_need_the_allowlist() {
+        let port = serve().await;
+        let url = format!("http://127.0.0.1:{}/photo.png", port);
+        let error = ImageFetcher::new().fetch(&url).await.unwrap_err().to_string();
+        assert_eq!(error, "Failed to fetch image: 127.0.0.1 is not a reachable public host");
+        let error = ImageFetcher::new()
+            .fetch(&format!("http://localhost:{}/photo.png", port)).await
+            .unwrap_err();
+        assert!(error.to_string().contains("localhost is not a reachable public host"));
+        let error = ImageFetcher::new().fetch("http://unresolvable.invalid/photo.png").await.unwrap_err();
+        assert!(error.to_string().contains("unresolvable.invalid is not a reachable public host"));
+        assert!(ImageFetcher::new().fetch("file:///etc/passwd").await.is_err());
+
+        let fetcher = ImageFetcher::new().with_allowlist(vec!["127.0.0.0/8".parse().unwrap()]);
+        assert_eq!(fetcher.fetch(&url).await.unwrap(), b"pixel");
+    }
+
+    #[tokio::test]
+    async fn test_fetched_responses_are_checked() {
+        let port = serve().await;
+        let url = |path: &str| format!("http://127.0.0.1:{}{}", port, path);
+        let fetcher = ImageFetcher::new()
+            .with_allowlist(vec!["127.0.0.1".parse().unwrap()])
+            .with_max_bytes(32)
+            .with_max_redirects(2);
+
+        assert_eq!(fetcher.fetch(&url("/redirect")).await.unwrap(), b"pixel");
+        let error = fetcher.fetch(&url("/loop")).await.unwrap_err().to_string();
+        assert!(error.contains("More than 2 redirects"), "{}", error);
+        let error = fetcher.fetch(&url("/page")).await.unwrap_err().to_string();
+        assert!(error.contains("Expected an image"), "{}", error);
+        let error = fetcher.fetch(&url("/large.png")).await.unwrap_err().to_string();
+        assert!(error.contains("larger than 32 bytes"), "{}", error);
+        let error = fetcher.fetch(&url("/missing.png")).await.unwrap_err().to_string();
+        assert!(error.contains("404"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_request_budget_is_shared_by_fetches() {
+        let port = serve().await;
+        let url = format!("http://127.0.0.1:{}/photo.png", port);
+        let fetcher = ImageFetcher::new()
+            .with_allowlist(vec!["127.0.0.1".parse().unwrap()])
+            .with_request_limits(3, 8);
+
+        let mut budget = fetcher.budget();
+        assert_eq!(fetcher.fetch_within(&url, &mut budget).await.unwrap(), b"pixel");
+        let error = fetcher.fetch_within(&url, &mut budget).await.unwrap_err().to_string();
+        assert!(error.contains("can total at most 8 bytes"), "{}", error);
+        fetcher.fetch_within(&url, &mut budget).await.unwrap_err();
+        let error = fetcher.fetch_within(&url, &mut budget).await.unwrap_err().to_string();
+        assert!(error.contains("At most 3 images can be fetched"), "{}", error);
+
+        // Every request starts with a full budget
+        assert_eq!(fetcher.fetch(&url).await.unwrap(), b"pixel");
+    }
+
+    #[test]
+    fn test_public_addresses_and_allowlist_entries() {
+        for ip in ["8.8.8.8", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["198.18.0.1", "198.19.255.255", "192.0.0.8", "::127.0.0.1", "64:ff9b::a9fe:a9fe", "64:ff9b::10.0.0.1", "2002:c0a8:0101::1", "2002:7f00:1::"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["64:ff9b::8.8.8.8", "2002:0808:0808::1", "198.20.0.1"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+
+        let network: AllowedHost = "10.0.0.0/8".parse().unwrap();
+        assert!(network.contains("10.20.30.40".parse().unwrap()));
+        assert!(!network.contains("11.0.0.1".parse().unwrap()));
+        assert_eq!("CMS.internal".parse::<AllowedHost>().unwrap(), AllowedHost::Name("cms.internal".to_string()));
+        assert!("10.0.0.0/33".parse::<AllowedHost>().is_err());
+    }
+}
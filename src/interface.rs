@@ -0,0 +1,346 @@
+use crate::{
+    errors::ProcessorError,
+    extraction::{ parse_details, CategoryExtraction },
+    prompts::{ ContentCategory, BOOLEAN, NUMBER },
+    utils::xml_escape,
+};
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+use std::collections::HashSet;
+
+/// Details read from a screenshot of a user interface.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InterfaceDetails {
+    pub application: String,
+    pub platform: String,
+    pub has_error_message: bool,
+    /// Flat list of components; the hierarchy is given by `parent`
+    pub components: Vec<Component>,
+}
+
+/// A visible UI element. Coordinates are fractions of the image width and height.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Component {
+    pub id: String,
+    /// Id of the enclosing component, empty for top-level components
+    pub parent: String,
+    /// `window`, `toolbar`, `form`, `button`, `input`, `checkbox`, `label`, `link`, ...
+    #[serde(rename = "type")]
+    pub component_type: String,
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// A component with its children, as returned by [`InterfaceDetails::tree`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComponentNode {
+    #[serde(flatten)]
+    pub component: Component,
+    pub children: Vec<ComponentNode>,
+}
+
+impl CategoryExtraction for InterfaceDetails {
+    fn handles(category: &ContentCategory) -> bool {
+        matches!(category, ContentCategory::Screenshot { .. } | ContentCategory::UserInterface)
+    }
+
+    fn structure(category: &ContentCategory) -> Option<Value> {
+        if !Self::handles(category) {
+            return None;
+        }
+        Some(
+            json!({
+                "application": "string",
+                "platform": "string",
+                "has_error_message": BOOLEAN,
+                "components": [{
+                    "id": "string",
+                    "parent": "parent id or empty",
+                    "type": "string",
+                    "text": "string",
+                    "x": NUMBER,
+                    "y": NUMBER,
+                    "width": NUMBER,
+                    "height": NUMBER
+                }]
+            })
+        )
+    }
+
+    fn instructions(category: &ContentCategory) -> Option<&'static str> {
+        Self::handles(category).then_some(
+            "List every visible component from top to bottom and left to right: containers, buttons, inputs, checkboxes, labels, links, icons and images. Give each a unique id and set parent to the id of the container it sits in. Copy visible text exactly. Give x, y, width and height of its bounding box as fractions of the image width and height between 0 and 1."
+        )
+    }
+
+    fn from_details(_category: &ContentCategory, details: &Value) -> Result<Self, ProcessorError> {
+        parse_details::<Self>(details).map(|mut details| {
+            details.clamp_boxes();
+            details
+        })
+    }
+}
+
+impl InterfaceDetails {
+    // Keeps bounding boxes inside the image
+    fn clamp_boxes(&mut self) {
+        for c in &mut self.components {
+            c.x = c.x.clamp(0.0, 1.0);
+            c.y = c.y.clamp(0.0, 1.0);
+            c.width = c.width.clamp(0.0, 1.0 - c.x);
+            c.height = c.height.clamp(0.0, 1.0 - c.y);
+        }
+    }
+
+    /// Builds the component hierarchy. Components whose parent is unknown, or that
+    /// would form a cycle, become top-level components. Components without an id can't
+    /// hold children, and children of a duplicated id go to the first component placed
+    /// with it.
+    pub fn tree(&self) -> Vec<ComponentNode> {
+        let ids: HashSet<&str> = self.components
+            .iter()
+            .map(|c| c.id.as_str())
+            .filter(|id| !id.is_empty())
+            .collect();
+        let is_root = |c: &Component| {
+            c.parent.is_empty() || c.parent == c.id || !ids.contains(c.parent.as_str())
+        };
+
+        let mut placed = vec![false; self.components.len()];
+        let mut roots: Vec<ComponentNode> = (0..self.components.len())
+            .filter(|&index| is_root(&self.components[index]))
+            .filter_map(|index| self.node(index, &mut placed))
+            .collect();
+        // Whatever is left is part of a parent cycle
+        for index in 0..self.components.len() {
+            if !placed[index] {
+                roots.extend(self.node(index, &mut placed));
+            }
+        }
+        roots
+    }
+
+    fn node(&self, index: usize, placed: &mut [bool]) -> Option<ComponentNode> {
+        if placed[index] {
+            return None;
+        }
+        placed[index] = true;
+        let component = &self.components[index];
+        let children = if component.id.is_empty() {
+            Vec::new()
+        } else {
+            (0..self.components.len())
+                .filter(|&child| {
+                    let c = &self.components[child];
+                    c.parent == component.id && c.id != component.id
+                })
+                .filter_map(|child| self.node(child, placed))
+                .collect()
+        };
+        Some(ComponentNode { component: component.clone(), children })
+    }
+
+    /// Renders one line per component, indented by depth, for diffing between builds.
+    ///
+    /// Ids are left out because the model picks them freshly on every run, and boxes
+    /// are rounded to two decimals so small measurement noise doesn't show up as a change.
+    pub fn outline(&self) -> String {
+        fn write(nodes: &[ComponentNode], depth: usize, out: &mut String) {
+            for node in nodes {
+                let c = &node.component;
+                out.push_str(&"  ".repeat(depth));
+                out.push_str(&c.component_type);
+                if !c.text.is_empty() {
+                    out.push_str(&format!(" {:?}", c.text));
+                }
+                out.push_str(
+                    &format!(" [{:.2} {:.2} {:.2} {:.2}]\n", c.x, c.y, c.width, c.height)
+                );
+                write(&node.children, depth + 1, out);
+            }
+        }
+        let mut out = String::new();
+        write(&self.tree(), 0, &mut out);
+        out
+    }
+
+    /// Renders an HTML page that lays the components out at their detected positions.
+    pub fn to_html(&self) -> String {
+        fn write(nodes: &[ComponentNode], parent: (f32, f32, f32, f32), depth: usize, out: &mut String) {
+            let (px, py, pw, ph) = parent;
+            let percent = |value: f32, total: f32| {
+                if total > 0.0 { (value / total) * 100.0 } else { 0.0 }
+            };
+            for node in nodes {
+                let c = &node.component;
+                let style = format!(
+                    "left:{:.1}%;top:{:.1}%;width:{:.1}%;height:{:.1}%",
+                    percent(c.x - px, pw),
+                    percent(c.y - py, ph),
+                    percent(c.width, pw),
+                    percent(c.height, ph)
+                );
+                let class = css_class(&c.component_type);
+                let text = xml_escape(&c.text);
+                let indent = "  ".repeat(depth + 2);
+                // Void elements carry their text in an attribute and can't hold children
+                let (tag, attributes, void) = match class.as_str() {
+                    "button" => ("button", String::new(), false),
+                    "link" => ("a", " href=\"#\"".to_string(), false),
+                    "input" | "text-field" | "search" =>
+                        ("input", format!(" placeholder=\"{}\"", text), true),
+                    "checkbox" | "radio" =>
+                        ("input", format!(" type=\"{}\" title=\"{}\"", class, text), true),
+                    "image" | "icon" => ("img", format!(" alt=\"{}\"", text), true),
+                    _ => ("div", String::new(), false),
+                };
+                out.push_str(
+                    &format!("{}<{} class=\"{}\" style=\"{}\"{}>", indent, tag, class, style, attributes)
+                );
+                if void {
+                    out.push('\n');
+                    write(&node.children, parent, depth, out);
+                } else if node.children.is_empty() {
+                    out.push_str(&format!("{}</{}>\n", text, tag));
+                } else {
+                    out.push_str(&format!("{}\n", text));
+                    write(&node.children, (c.x, c.y, c.width, c.height), depth + 1, out);
+                    out.push_str(&format!("{}</{}>\n", indent, tag));
+                }
+            }
+        }
+
+        let title = if self.application.is_empty() { "Screen" } else { &self.application };
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n.screen {{ position: relative; width: 100%; aspect-ratio: 16 / 10; }}\n.screen * {{ position: absolute; box-sizing: border-box; margin: 0; border: 1px solid #999; font: 12px sans-serif; }}\n</style>\n</head>\n<body>\n  <div class=\"screen\">\n",
+            xml_escape(title)
+        );
+        write(&self.tree(), (0.0, 0.0, 1.0, 1.0), 0, &mut html);
+        html.push_str("  </div>\n</body>\n</html>\n");
+        html
+    }
+}
+
+fn css_class(component_type: &str) -> String {
+    let class: String = component_type
+        .trim()
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    if class.is_empty() { "component".to_string() } else { class }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extraction::analysis_with_details;
+
+    fn component(id: &str, parent: &str, component_type: &str, text: &str, bounds: [f32; 4]) -> Component {
+        Component {
+            id: id.to_string(),
+            parent: parent.to_string(),
+            component_type: component_type.to_string(),
+            text: text.to_string(),
+            x: bounds[0],
+            y: bounds[1],
+            width: bounds[2],
+            height: bounds[3],
+        }
+    }
+
+    fn login_screen() -> InterfaceDetails {
+        InterfaceDetails {
+            application: "Acme".to_string(),
+            components: vec![
+                component("form", "", "form", "", [0.1, 0.2, 0.8, 0.6]),
+                component("email", "form", "input", "Email", [0.2, 0.3, 0.6, 0.1]),
+                component("submit", "form", "button", "Sign in", [0.5, 0.6, 0.2, 0.1]),
+                component("a", "b", "label", "orphan", [0.0, 0.0, 0.1, 0.1]),
+                component("b", "a", "label", "", [0.0, 0.0, 0.1, 0.1])
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tree_handles_cycles() {
+        let tree = login_screen().tree();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].children.len(), 2);
+        assert_eq!(tree[1].component.id, "a");
+        assert_eq!(tree[1].children[0].component.id, "b");
+    }
+
+    #[test]
+    fn test_tree_keeps_duplicate_ids() {
+        let details = InterfaceDetails {
+            components: vec![
+                component("row", "", "row", "", [0.0, 0.0, 1.0, 0.1]),
+                component("row", "", "row", "", [0.0, 0.1, 1.0, 0.1]),
+                component("ok", "row", "button", "OK", [0.0, 0.0, 0.2, 0.1])
+            ],
+            ..Default::default()
+        };
+        let tree = details.tree();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].children[0].component.text, "OK");
+        assert!(tree[1].children.is_empty());
+        assert_eq!(details.outline().lines().count(), 3);
+        assert!(details.to_html().contains(">OK</button>"));
+    }
+
+    #[test]
+    fn test_tree_never_nests_under_empty_ids() {
+        let details = InterfaceDetails {
+            components: vec![
+                component("", "", "image", "Logo", [0.0, 0.0, 0.2, 0.1]),
+                component("title", "", "label", "Welcome", [0.0, 0.1, 0.5, 0.1]),
+                component("", "", "button", "Start", [0.0, 0.2, 0.2, 0.1])
+            ],
+            ..Default::default()
+        };
+        let tree = details.tree();
+        assert_eq!(tree.len(), 3);
+        assert!(tree.iter().all(|node| node.children.is_empty()));
+    }
+
+    #[test]
+    fn test_outline() {
+        assert_eq!(
+            login_screen().outline(),
+            "form [0.10 0.20 0.80 0.60]\n  input \"Email\" [0.20 0.30 0.60 0.10]\n  button \"Sign in\" [0.50 0.60 0.20 0.10]\nlabel \"orphan\" [0.00 0.00 0.10 0.10]\n  label [0.00 0.00 0.10 0.10]\n"
+        );
+    }
+
+    #[test]
+    fn test_html_skeleton() {
+        let html = login_screen().to_html();
+        assert!(html.contains("<title>Acme</title>"));
+        assert!(
+            html.contains(
+                "<input class=\"input\" style=\"left:12.5%;top:16.7%;width:75.0%;height:16.7%\" placeholder=\"Email\">\n"
+            )
+        );
+        assert!(html.contains(">Sign in</button>\n"));
+        assert!(html.contains("Sign in</button>\n    </div>\n"));
+    }
+
+    #[test]
+    fn test_boxes_are_clamped() {
+        let result = analysis_with_details(
+            json!({ "components": [{ "id": "x", "type": "button", "x": 0.9, "y": -0.1, "width": 0.5, "height": 0.2 }] })
+        );
+        let details = InterfaceDetails::from_result(&ContentCategory::UserInterface, &result)
+            .unwrap()
+            .unwrap();
+        let button = &details.components[0];
+        assert_eq!((button.y, button.height), (0.0, 0.2));
+        assert!((button.width - 0.1).abs() < 1e-6);
+    }
+}
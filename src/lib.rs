@@ -26,14 +26,50 @@
 //! }
 //! ```
 
+pub mod accessibility;
+pub mod analysis;
+pub mod categories;
+pub mod charts;
+pub mod comparison;
+pub mod detection;
+pub mod diagrams;
+pub mod documents;
 pub mod errors;
+pub mod extraction;
+pub mod fetch;
+pub mod interface;
+pub mod language;
+pub mod ocr;
+pub mod preprocess;
 pub mod processor;
 pub mod prompts;
 pub mod providers;
+pub mod questions;
+pub mod repair;
+pub mod sessions;
+pub mod tables;
+pub mod templates;
 pub mod utils;
 
 // Re-export commonly used types
+pub use accessibility::AccessibilityDescription;
+pub use analysis::AnalysisResult;
+pub use charts::{ChartData, ChartExtraction};
+pub use comparison::{Comparison, Difference};
+pub use detection::Detections;
+pub use diagrams::{DiagramData, DiagramExtraction};
+pub use documents::{DocumentDetails, DocumentExtraction};
 pub use errors::ProcessorError;
-pub use processor::ImageProcessor;
-pub use prompts::{ImagePrompt, PromptFormat};
-pub use providers::{AIProvider, TokenUsage};
+pub use extraction::CategoryExtraction;
+pub use fetch::ImageFetcher;
+pub use interface::{ComponentNode, InterfaceDetails};
+pub use ocr::TextLayout;
+pub use preprocess::{ Preprocessing, RedactionStyle, Region };
+pub use processor::{AnalysisOutput, ImageProcessor};
+pub use prompts::{AnalysisConfig, ContentCategory, ImagePrompt, PromptFormat};
+pub use providers::{AIProvider, GenerationParams, ImageDetail, Message, Role, TokenUsage};
+pub use questions::{Answer, AnswerType};
+pub use repair::RepairReport;
+pub use sessions::{Conversation, SessionStore};
+pub use tables::{Table, TableGrid};
+pub use templates::{FewShotExample, PromptRegistry, TemplateContext};
@@ -0,0 +1,340 @@
+use crate::{ errors::ProcessorError, repair::repair_json, utils::{ xml_escape, BoundingBox } };
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+
+/// Text of an image with word positions, grouped into lines and paragraphs.
+///
+/// Paragraphs, lines and words are listed in reading order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextLayout {
+    /// Size in pixels of the image the boxes refer to
+    pub image_width: u32,
+    pub image_height: u32,
+    /// ISO 639-1 code of the main language, empty when unknown
+    pub language: String,
+    pub paragraphs: Vec<Paragraph>,
+    /// `builtin`, or the `name@version` of the prompt template used
+    #[serde(skip_deserializing)]
+    pub prompt_version: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Paragraph {
+    pub bbox: BoundingBox,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Line {
+    pub bbox: BoundingBox,
+    pub words: Vec<Word>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Word {
+    pub text: String,
+    #[serde(flatten)]
+    pub bbox: BoundingBox,
+    pub confidence: f32,
+}
+
+/// Instructions for the text extraction mode.
+pub const PROMPT: &str =
+    r#"Transcribe all text in this image exactly as written, word by word.
+
+- List paragraphs in natural reading order: follow columns top to bottom before moving to the next column, and read captions and sidebars after the text they belong to.
+- Split each paragraph into its printed lines and each line into words, keeping punctuation attached to its word.
+- Give x, y, width and height of each word's bounding box as fractions of the image width and height between 0 and 1.
+- confidence is how legible the word is, from 0.0 to 1.0.
+- language is the ISO 639-1 code of the main language.
+
+Respond with JSON only:
+{
+    "language": "string",
+    "paragraphs": [{
+        "lines": [{
+            "words": [{ "text": "string", "x": number, "y": number, "width": number, "height": number, "confidence": 0.0-1.0 }]
+        }]
+    }]
+}"#;
+
+/// JSON Schema for the text extraction output, for providers that enforce schemas.
+pub fn schema() -> Value {
+    let number = json!({ "type": "number" });
+    let word = json!({
+        "type": "object",
+        "properties": {
+            "text": { "type": "string" },
+            "x": number,
+            "y": number,
+            "width": number,
+            "height": number,
+            "confidence": { "type": "number", "minimum": 0.0, "maximum": 1.0 }
+        },
+        "required": ["text", "x", "y", "width", "height", "confidence"],
+        "additionalProperties": false
+    });
+    let array_of = |name: &str, items: Value| {
+        json!({
+            "type": "object",
+            "properties": { name: { "type": "array", "items": items } },
+            "required": [name],
+            "additionalProperties": false
+        })
+    };
+    let paragraph = array_of("lines", array_of("words", word));
+    json!({
+        "type": "object",
+        "properties": {
+            "language": { "type": "string" },
+            "paragraphs": { "type": "array", "items": paragraph }
+        },
+        "required": ["language", "paragraphs"],
+        "additionalProperties": false
+    })
+}
+
+impl TextLayout {
+    /// Parses provider output for an image of the given pixel size, dropping empty
+    /// words, lines and paragraphs and computing line and paragraph boxes from their words.
+    pub fn parse(output: &str, image_width: u32, image_height: u32) -> Result<Self, ProcessorError> {
+        let (json, _) = repair_json(output);
+        let mut layout: Self = serde_json::from_str(&json).map_err(|e| {
+            ProcessorError::ResponseParseError(
+                format!("Failed to parse text layout: {}. Response text: {}", e, output)
+            )
+        })?;
+        layout.image_width = image_width;
+        layout.image_height = image_height;
+
+        for paragraph in &mut layout.paragraphs {
+            for line in &mut paragraph.lines {
+                line.words.retain(|w| !w.text.trim().is_empty());
+                for word in &mut line.words {
+                    word.bbox = word.bbox.clamp();
+                    word.confidence = word.confidence.clamp(0.0, 1.0);
+                }
+                line.bbox = BoundingBox::union(line.words.iter().map(|w| w.bbox));
+            }
+            paragraph.lines.retain(|l| !l.words.is_empty());
+            paragraph.bbox = BoundingBox::union(paragraph.lines.iter().map(|l| l.bbox));
+        }
+        layout.paragraphs.retain(|p| !p.lines.is_empty());
+        Ok(layout)
+    }
+
+    /// All words in reading order.
+    pub fn words(&self) -> impl Iterator<Item = &Word> {
+        self.paragraphs
+            .iter()
+            .flat_map(|p| &p.lines)
+            .flat_map(|l| &l.words)
+    }
+
+    // Corners in pixels: left, top, right, bottom
+    fn pixels(&self, bbox: &BoundingBox) -> [u32; 4] {
+        let (w, h) = (self.image_width as f32, self.image_height as f32);
+        [
+            (bbox.x * w).round() as u32,
+            (bbox.y * h).round() as u32,
+            ((bbox.x + bbox.width) * w).round() as u32,
+            ((bbox.y + bbox.height) * h).round() as u32,
+        ]
+    }
+
+    /// Plain text with one line per printed line and a blank line between paragraphs.
+    pub fn to_text(&self) -> String {
+        let paragraphs: Vec<String> = self.paragraphs
+            .iter()
+            .map(|p| {
+                p.lines
+                    .iter()
+                    .map(|l| {
+                        l.words
+                            .iter()
+                            .map(|w| w.text.as_str())
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .collect();
+        let mut text = paragraphs.join("\n\n");
+        text.push('\n');
+        text
+    }
+
+    /// Renders hOCR 1.2, with pixel boxes and word confidences as `x_wconf`.
+    pub fn to_hocr(&self) -> String {
+        let title = |bbox: &BoundingBox| {
+            let [left, top, right, bottom] = self.pixels(bbox);
+            format!("bbox {} {} {} {}", left, top, right, bottom)
+        };
+        let lang = if self.language.is_empty() {
+            String::new()
+        } else {
+            format!(" xml:lang=\"{0}\" lang=\"{0}\"", xml_escape(&self.language))
+        };
+
+        let mut hocr = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">\n<html xmlns=\"http://www.w3.org/1999/xhtml\"{}>\n<head>\n<title></title>\n<meta http-equiv=\"Content-Type\" content=\"text/html; charset=utf-8\" />\n<meta name=\"ocr-system\" content=\"eyeris\" />\n<meta name=\"ocr-capabilities\" content=\"ocr_page ocr_par ocr_line ocrx_word\" />\n</head>\n<body>\n<div class=\"ocr_page\" id=\"page_1\" title=\"bbox 0 0 {} {}\">\n",
+            lang,
+            self.image_width,
+            self.image_height
+        );
+        let (mut line_id, mut word_id) = (0, 0);
+        for (p, paragraph) in self.paragraphs.iter().enumerate() {
+            hocr.push_str(
+                &format!("<p class=\"ocr_par\" id=\"par_{}\" title=\"{}\">\n", p + 1, title(&paragraph.bbox))
+            );
+            for line in &paragraph.lines {
+                line_id += 1;
+                hocr.push_str(
+                    &format!("<span class=\"ocr_line\" id=\"line_{}\" title=\"{}\">", line_id, title(&line.bbox))
+                );
+                let words: Vec<String> = line.words
+                    .iter()
+                    .map(|word| {
+                        word_id += 1;
+                        format!(
+                            "<span class=\"ocrx_word\" id=\"word_{}\" title=\"{}; x_wconf {}\">{}</span>",
+                            word_id,
+                            title(&word.bbox),
+                            (word.confidence * 100.0).round() as u32,
+                            xml_escape(&word.text)
+                        )
+                    })
+                    .collect();
+                hocr.push_str(&words.join(" "));
+                hocr.push_str("</span>\n");
+            }
+            hocr.push_str("</p>\n");
+        }
+        hocr.push_str("</div>\n</body>\n</html>\n");
+        hocr
+    }
+
+    /// Renders ALTO 4 XML with pixel measurements.
+    pub fn to_alto(&self) -> String {
+        let position = |bbox: &BoundingBox| {
+            let [left, top, right, bottom] = self.pixels(bbox);
+            format!(
+                "HPOS=\"{}\" VPOS=\"{}\" WIDTH=\"{}\" HEIGHT=\"{}\"",
+                left,
+                top,
+                right - left,
+                bottom - top
+            )
+        };
+        let lang = if self.language.is_empty() {
+            String::new()
+        } else {
+            format!(" LANG=\"{}\"", xml_escape(&self.language))
+        };
+
+        let mut alto = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<alto xmlns=\"http://www.loc.gov/standards/alto/ns-v4#\">\n  <Description>\n    <MeasurementUnit>pixel</MeasurementUnit>\n  </Description>\n  <Layout>\n    <Page ID=\"page_1\" PHYSICAL_IMG_NR=\"1\" WIDTH=\"{0}\" HEIGHT=\"{1}\">\n      <PrintSpace HPOS=\"0\" VPOS=\"0\" WIDTH=\"{0}\" HEIGHT=\"{1}\">\n",
+            self.image_width,
+            self.image_height
+        );
+        let (mut line_id, mut word_id) = (0, 0);
+        for (p, paragraph) in self.paragraphs.iter().enumerate() {
+            alto.push_str(
+                &format!(
+                    "        <TextBlock ID=\"block_{}\" {}{}>\n",
+                    p + 1,
+                    position(&paragraph.bbox),
+                    lang
+                )
+            );
+            for line in &paragraph.lines {
+                line_id += 1;
+                alto.push_str(
+                    &format!("          <TextLine ID=\"line_{}\" {}>\n", line_id, position(&line.bbox))
+                );
+                for (i, word) in line.words.iter().enumerate() {
+                    word_id += 1;
+                    if i > 0 {
+                        alto.push_str("            <SP/>\n");
+                    }
+                    alto.push_str(
+                        &format!(
+                            "            <String ID=\"string_{}\" CONTENT=\"{}\" {} WC=\"{:.2}\"/>\n",
+                            word_id,
+                            xml_escape(&word.text),
+                            position(&word.bbox),
+                            word.confidence
+                        )
+                    );
+                }
+                alto.push_str("          </TextLine>\n");
+            }
+            alto.push_str("        </TextBlock>\n");
+        }
+        alto.push_str("      </PrintSpace>\n    </Page>\n  </Layout>\n</alto>\n");
+        alto
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str =
+        r#"{
+        "language": "en",
+        "paragraphs": [
+            { "lines": [
+                { "words": [
+                    { "text": "Fish", "x": 0.1, "y": 0.1, "width": 0.2, "height": 0.1, "confidence": 0.98 },
+                    { "text": "&", "x": 0.35, "y": 0.1, "width": 0.05, "height": 0.1, "confidence": 0.9 },
+                    { "text": "Chips", "x": 0.45, "y": 0.12, "width": 0.3, "height": 0.1, "confidence": 0.95 }
+                ] },
+                { "words": [{ "text": "", "x": 0, "y": 0, "width": 0, "height": 0, "confidence": 0 }] }
+            ] },
+            { "lines": [
+                { "words": [{ "text": "£4.50", "x": 0.1, "y": 0.5, "width": 0.2, "height": 0.1, "confidence": 0.8 }] }
+            ] }
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_groups_and_boxes() {
+        let layout = TextLayout::parse(OUTPUT, 200, 100).unwrap();
+        assert_eq!(layout.paragraphs.len(), 2);
+        assert_eq!(layout.paragraphs[0].lines.len(), 1);
+        let line = layout.paragraphs[0].lines[0].bbox;
+        assert!((line.x - 0.1).abs() < 1e-6 && (line.width - 0.65).abs() < 1e-6);
+        assert!((line.height - 0.12).abs() < 1e-6);
+        assert_eq!(layout.words().count(), 4);
+        assert_eq!(layout.to_text(), "Fish & Chips\n\n£4.50\n");
+    }
+
+    #[test]
+    fn test_hocr_export() {
+        let hocr = TextLayout::parse(OUTPUT, 200, 100).unwrap().to_hocr();
+        assert!(hocr.contains("<div class=\"ocr_page\" id=\"page_1\" title=\"bbox 0 0 200 100\">"));
+        assert!(hocr.contains("<span class=\"ocr_line\" id=\"line_1\" title=\"bbox 20 10 150 22\">"));
+        assert!(
+            hocr.contains("<span class=\"ocrx_word\" id=\"word_2\" title=\"bbox 70 10 80 20; x_wconf 90\">&amp;</span>")
+        );
+        assert!(hocr.contains("xml:lang=\"en\""));
+    }
+
+    #[test]
+    fn test_alto_export() {
+        let alto = TextLayout::parse(OUTPUT, 200, 100).unwrap().to_alto();
+        assert!(alto.contains("<Page ID=\"page_1\" PHYSICAL_IMG_NR=\"1\" WIDTH=\"200\" HEIGHT=\"100\">"));
+        assert!(
+            alto.contains(
+                "<String ID=\"string_1\" CONTENT=\"Fish\" HPOS=\"20\" VPOS=\"10\" WIDTH=\"40\" HEIGHT=\"10\" WC=\"0.98\"/>\n            <SP/>"
+            )
+        );
+        assert!(alto.contains("<TextBlock ID=\"block_2\" HPOS=\"20\" VPOS=\"50\" WIDTH=\"40\" HEIGHT=\"10\" LANG=\"en\">"));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::errors::ProcessorError;
+use image::{ imageops, DynamicImage, GenericImage, GenericImageView, Rgba };
+use serde::{ Deserialize, Serialize };
+use std::str::FromStr;
+
+/// A rectangle in pixel coordinates of the original image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactionStyle {
+    /// Fill the region with solid black. Irreversible, recommended for sensitive data.
+    #[default]
+    Blackout,
+    /// Heavily blur the region, keeping the overall layout recognizable.
+    Blur,
+}
+
+/// Steps applied to the decoded image before it is sent to the provider.
+///
+/// Redactions are applied first, then the crop, so all regions use the
+/// coordinates of the original image.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Preprocessing {
+    #[serde(default)]
+    pub crop: Option<Region>,
+    #[serde(default)]
+    pub redact: Vec<Region>,
+    #[serde(default)]
+    pub redaction_style: RedactionStyle,
+}
+
+impl Region {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Clips the region to the image bounds, failing if nothing is left.
+    fn clamp_to(&self, width: u32, height: u32) -> Result<Region, ProcessorError> {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        let clipped = Region {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        };
+
+        if clipped.width == 0 || clipped.height == 0 {
+            return Err(
+                ProcessorError::InvalidRegion(
+                    format!("Region {} lies outside the {}x{} image", self, width, height)
+                )
+            );
+        }
+        Ok(clipped)
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl FromStr for Region {
+    type Err = ProcessorError;
+
+    /// Parses `x,y,width,height`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|p| p.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ProcessorError::InvalidRegion(format!("Invalid region {:?}: {}", s, e)))?;
+
+        match parts[..] {
+            [x, y, width, height] => Ok(Region::new(x, y, width, height)),
+            _ =>
+                Err(
+                    ProcessorError::InvalidRegion(
+                        format!("Invalid region {:?}: expected x,y,width,height", s)
+                    )
+                ),
+        }
+    }
+}
+
+impl FromStr for RedactionStyle {
+    type Err = ProcessorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "blackout" => Ok(Self::Blackout),
+            "blur" => Ok(Self::Blur),
+            other =>
+                Err(
+                    ProcessorError::InvalidRegion(
+                        format!("Unknown redaction style {:?}: expected blackout or blur", other)
+                    )
+                ),
+        }
+    }
+}
+
+impl Preprocessing {
+    pub fn is_empty(&self) -> bool {
+        self.crop.is_none() && self.redact.is_empty()
+    }
+
+    pub fn apply(&self, img: &DynamicImage) -> Result<DynamicImage, ProcessorError> {
+        let mut output = img.clone();
+        let (width, height) = img.dimensions();
+
+        for region in &self.redact {
+            let region = region.clamp_to(width, height)?;
+            match self.redaction_style {
+                RedactionStyle::Blackout => {
+                    let black = Rgba([0, 0, 0, 255]);
+                    for y in region.y..region.y + region.height {
+                        for x in region.x..region.x + region.width {
+                            output.put_pixel(x, y, black);
+                        }
+                    }
+                }
+                RedactionStyle::Blur => {
+                    let patch = output.crop_imm(region.x, region.y, region.width, region.height);
+                    // Scale the blur with the region so text of any size becomes unreadable
+                    let sigma = (region.width.min(region.height) as f32 / 4.0).max(8.0);
+                    let blurred = patch.blur(sigma);
+                    output
+                        .copy_from(&blurred, region.x, region.y)
+                        .map_err(|e| ProcessorError::ImageError(e.to_string()))?;
+                }
+            }
+        }
+
+        if let Some(crop) = self.crop {
+            let crop = crop.clamp_to(width, height)?;
+            output = DynamicImage::ImageRgba8(
+                imageops::crop_imm(&output, crop.x, crop.y, crop.width, crop.height).to_image()
+            );
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ ImageBuffer, Rgb };
+
+    fn white_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_pixel(width, height, Rgb([255, 255, 255])))
+    }
+
+    #[test]
+    fn test_region_parsing() {
+        assert_eq!("1, 2,3,4".parse::<Region>().unwrap(), Region::new(1, 2, 3, 4));
+        assert!("1,2,3".parse::<Region>().is_err());
+        assert!("a,2,3,4".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn test_blackout_redaction() {
+        let preprocessing = Preprocessing {
+            redact: vec![Region::new(2, 2, 4, 4)],
+            ..Default::default()
+        };
+        let output = preprocessing.apply(&white_image(10, 10)).unwrap();
+        assert_eq!(output.get_pixel(3, 3), Rgba([0, 0, 0, 255]));
+        assert_eq!(output.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_crop_after_redaction() {
+        let preprocessing = Preprocessing {
+            crop: Some(Region::new(5, 5, 100, 100)),
+            redact: vec![Region::new(0, 0, 6, 6)],
+            ..Default::default()
+        };
+        let output = preprocessing.apply(&white_image(10, 10)).unwrap();
+        assert_eq!(output.dimensions(), (5, 5));
+        assert_eq!(output.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(output.get_pixel(1, 1), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_region_outside_image() {
+        let preprocessing = Preprocessing {
+            crop: Some(Region::new(20, 20, 5, 5)),
+            ..Default::default()
+        };
+        assert!(preprocessing.apply(&white_image(10, 10)).is_err());
+    }
+}
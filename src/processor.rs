@@ -1,34 +1,418 @@
 use crate::{
+    accessibility::{ self, AccessibilityDescription },
+    analysis::AnalysisResult,
+    comparison::{ self, Comparison },
+    categories::{
+        classification_prompt,
+        classification_schema,
+        CategoryClassification,
+        MIN_CLASSIFICATION_CONFIDENCE,
+    },
     errors::ProcessorError,
-    prompts::{ ImagePrompt, PromptFormat },
-    providers::{ AIProvider, Provider, TokenUsage },
+    ocr::{ self, TextLayout },
+    preprocess::Preprocessing,
+    prompts::{ json_schema, AnalysisConfig, ContentCategory, ImagePrompt, PromptFormat },
+    providers::{ AIProvider, GenerationParams, Message, Provider, TokenUsage },
+    questions::{ self, Answer, AnswerType },
+    repair::{ repair_json, strip_code_fence, RepairReport },
+    sessions::{ Conversation, FOLLOW_UP_INSTRUCTIONS, MAX_QUESTIONS },
+    templates::{ with_examples_version, FewShotExample, PromptRegistry, TemplateContext, BUILTIN_VERSION },
+    utils::extract_palette,
 };
 use base64::Engine;
-use std::time::Instant;
-use tracing::{ info, debug, error };
+use image::DynamicImage;
+use std::{ borrow::Cow, io::Cursor, sync::Arc, time::Instant };
+use tracing::{ info, debug, error, warn };
 
 pub struct ImageProcessor {
     provider: Box<dyn Provider>,
     prompt_format: PromptFormat,
+    analysis_config: AnalysisConfig,
+    palette_size: usize,
+    preprocessing: Preprocessing,
+    max_continuations: usize,
+    native_schema: bool,
+    two_stage: bool,
+    classifier: Option<Box<dyn Provider>>,
+    prompts: Option<Arc<PromptRegistry>>,
 }
 
+/// Everything produced by [`ImageProcessor::process_output`].
+#[derive(Debug, Clone)]
+pub struct AnalysisOutput {
+    pub analysis: String,
+    pub token_usage: TokenUsage,
+    pub repairs: RepairReport,
+    /// Category the analysis targeted, either configured or picked by the
+    /// classification stage of two-stage analysis.
+    pub category: Option<ContentCategory>,
+    /// Pixel size of the analyzed image, after cropping
+    pub image_width: u32,
+    pub image_height: u32,
+    /// `builtin`, or the `name@version` of the prompt template used
+    pub prompt_version: String,
+    /// The request and the analysis, for follow-up questions with [`ImageProcessor::ask`]
+    pub conversation: Conversation,
+}
+
+/// Longest edge of the downscaled image sent to the classification stage.
+const CLASSIFICATION_IMAGE_SIZE: u32 = 512;
+
+/// Characters of truncated output shown to the model when asking it to continue.
+const CONTINUATION_CONTEXT_CHARS: usize = 500;
+
+/// Number of dominant colors measured locally and passed to the provider.
+const DEFAULT_PALETTE_SIZE: usize = 6;
+
 impl ImageProcessor {
     pub fn new(provider: AIProvider, model: Option<String>, format: Option<PromptFormat>) -> Self {
-        let provider: Box<dyn Provider> = match provider {
-            AIProvider::OpenAI => Box::new(crate::providers::OpenAIProvider::new(model)),
-            AIProvider::Ollama => Box::new(crate::providers::OllamaProvider::new(model)),
-        };
-
         Self {
-            provider,
+            provider: provider.create(model),
             prompt_format: format.unwrap_or_default(),
+            analysis_config: AnalysisConfig::default(),
+            palette_size: DEFAULT_PALETTE_SIZE,
+            preprocessing: Preprocessing::default(),
+            max_continuations: 0,
+            native_schema: true,
+            two_stage: false,
+            classifier: None,
+            prompts: None,
         }
     }
 
+    /// Sets which analysis sections the prompt asks the provider for.
+    pub fn with_analysis_config(mut self, analysis_config: AnalysisConfig) -> Self {
+        self.analysis_config = analysis_config;
+        self
+    }
+
+    /// Sets temperature, output length and the other sampling settings of the analysis
+    /// requests; a separate classifier keeps its defaults.
+    pub fn with_generation_params(mut self, params: GenerationParams) -> Self {
+        self.provider.set_generation_params(params);
+        self
+    }
+
+    /// Sets the language the analysis is written in, as an ISO 639-1 code such as `ja`
+    /// or a name; shorthand for `AnalysisConfig::language`.
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.analysis_config.language = Some(language.to_string());
+        self
+    }
+
+    /// Keeps extracted text in its original language and adds its detected language and
+    /// a translation; shorthand for `AnalysisConfig::translate_text`.
+    pub fn with_translation(mut self, translate_text: bool) -> Self {
+        self.analysis_config.translate_text = translate_text;
+        self
+    }
+
+    /// Sets how many dominant colors are extracted and injected into the prompt.
+    /// A size of zero disables local palette extraction.
+    pub fn with_palette_size(mut self, palette_size: usize) -> Self {
+        self.palette_size = palette_size;
+        self
+    }
+
+    /// Sets the crop and redaction steps applied before the image leaves the process.
+    pub fn with_preprocessing(mut self, preprocessing: Preprocessing) -> Self {
+        self.preprocessing = preprocessing;
+        self
+    }
+
+    /// Sets how many times truncated JSON output is sent back to the model to be continued.
+    /// Truncated output is closed locally either way.
+    pub fn with_max_continuations(mut self, max_continuations: usize) -> Self {
+        self.max_continuations = max_continuations;
+        self
+    }
+
+    /// Sets whether JSON output is constrained through the provider's schema support.
+    /// When disabled, or unsupported, the full structure is spelled out in the prompt.
+    pub fn with_native_schema(mut self, native_schema: bool) -> Self {
+        self.native_schema = native_schema;
+        self
+    }
+
+    /// Enables classify-then-specialize analysis: a first call on a downscaled image picks
+    /// the `ContentCategory`, and the main call uses that category's prompt and fields.
+    /// Skipped when `AnalysisConfig::content_category` is already set.
+    pub fn with_two_stage(mut self, two_stage: bool) -> Self {
+        self.two_stage = two_stage;
+        self
+    }
+
+    /// Uses a separate, typically cheaper, model for the classification stage and
+    /// enables two-stage analysis.
+    pub fn with_classifier(mut self, provider: AIProvider, model: Option<String>) -> Self {
+        self.classifier = Some(provider.create(model));
+        self.two_stage = true;
+        self
+    }
+
+    /// Renders prompts from the registry's templates where it has one, instead of the
+    /// built-in prompts.
+    pub fn with_prompt_registry(mut self, prompts: Arc<PromptRegistry>) -> Self {
+        self.prompts = Some(prompts);
+        self
+    }
+
+    /// Renders the named template of a dedicated mode, or returns the built-in prompt.
+    fn mode_prompt(
+        &self,
+        name: &str,
+        builtin: String,
+        context: TemplateContext
+    ) -> Result<(String, String), ProcessorError> {
+        // Transcriptions stay in the language of the image.
+        let builtin = match self.analysis_config.language_instruction() {
+            Some(instruction) if name != "ocr" => format!("{}\n\n{}", builtin, instruction),
+            _ => builtin,
+        };
+        let rendered = match &self.prompts {
+            Some(prompts) => {
+                let context = context
+                    .with_config(&self.analysis_config)
+                    .with_variable("builtin", builtin.clone())
+                    .with_variable(
+                        "language",
+                        self.analysis_config.language_name().unwrap_or_default()
+                    );
+                prompts.render(name, &context)?
+            }
+            None => None,
+        };
+        Ok(rendered.unwrap_or((builtin, BUILTIN_VERSION.to_string())))
+    }
+
+    /// The registry's few-shot examples for the template and category. Examples hold a
+    /// single image, so they are only used with single-image requests.
+    fn examples(
+        &self,
+        template: &str,
+        category: Option<&ContentCategory>,
+        image_count: usize
+    ) -> Vec<&FewShotExample> {
+        match &self.prompts {
+            Some(prompts) if image_count == 1 => prompts.examples(template, category),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The version of the prompt, including the examples sent with it.
+    fn prompt_version(
+        &self,
+        version: String,
+        template: &str,
+        category: Option<&ContentCategory>,
+        image_count: usize
+    ) -> String {
+        with_examples_version(version, &self.examples(template, category, image_count))
+    }
+
+    /// Sends the images with the prompt, preceded by the few-shot examples for the
+    /// template and category as earlier turns of the conversation.
+    async fn send(
+        &self,
+        template: &str,
+        category: Option<&ContentCategory>,
+        base64_images: &[&str],
+        prompt: &str,
+        schema: Option<&serde_json::Value>
+    ) -> Result<(String, Option<TokenUsage>), ProcessorError> {
+        self.send_with_examples(template, category, base64_images, prompt, prompt, schema).await
+    }
+
+    /// Like [`ImageProcessor::send`], asking the examples with `example_prompt`, which
+    /// leaves out what only applies to the image analyzed, such as its palette.
+    async fn send_with_examples(
+        &self,
+        template: &str,
+        category: Option<&ContentCategory>,
+        base64_images: &[&str],
+        example_prompt: &str,
+        prompt: &str,
+        schema: Option<&serde_json::Value>
+    ) -> Result<(String, Option<TokenUsage>), ProcessorError> {
+        let mut messages = Vec::new();
+        for example in self.examples(template, category, base64_images.len()) {
+            messages.push(Message::user(example_prompt, vec![example.image.clone()]));
+            messages.push(Message::assistant(&example.response));
+        }
+        if !messages.is_empty() {
+            debug!("Sending {} few-shot examples for {}", messages.len() / 2, template);
+        }
+        messages.push(
+            Message::user(
+                prompt,
+                base64_images
+                    .iter()
+                    .map(|image| image.to_string())
+                    .collect()
+            )
+        );
+        self.provider.chat(&messages, schema).await
+    }
+
+    fn uses_native_schema(&self) -> bool {
+        self.native_schema &&
+            matches!(self.prompt_format, PromptFormat::Json) &&
+            self.provider.supports_json_schema()
+    }
+
+    fn uses_palette(&self) -> bool {
+        self.palette_size > 0 &&
+            self.analysis_config.color_analysis &&
+            matches!(self.prompt_format, PromptFormat::Json | PromptFormat::Detailed)
+    }
+
     pub async fn process(&self, image_data: &[u8]) -> Result<(String, TokenUsage), ProcessorError> {
+        let output = self.process_output(image_data).await?;
+        Ok((output.analysis, output.token_usage))
+    }
+
+    /// Like [`ImageProcessor::process`], also reporting how JSON output was repaired and
+    /// which category the classification stage picked.
+    pub async fn process_output(&self, image_data: &[u8]) -> Result<AnalysisOutput, ProcessorError> {
+        self.process_images(&[image_data]).await
+    }
+
+    /// Analyzes several images of one subject in a single request, such as the pages of a
+    /// document or photos from different angles, combining them into one analysis.
+    ///
+    /// Classification, the color palette and the reported image size use the first image;
+    /// the palette is skipped when there is more than one.
+    pub async fn process_images(&self, images: &[&[u8]]) -> Result<AnalysisOutput, ProcessorError> {
         let start = Instant::now();
-        debug!("Starting image processing with {} bytes", image_data.len());
+        debug!(
+            "Starting image processing of {} images with {} bytes",
+            images.len(),
+            images
+                .iter()
+                .map(|data| data.len())
+                .sum::<usize>()
+        );
+        if images.is_empty() {
+            return Err(ProcessorError::ImageError("No images to analyze".to_string()));
+        }
+
+        let loaded = images
+            .iter()
+            .map(|data| self.load(data))
+            .collect::<Result<Vec<_>, _>>()?;
+        let img = &loaded[0].0;
+
+        // Measure dominant colors locally so the model doesn't have to guess them
+        let palette = if self.uses_palette() && loaded.len() == 1 {
+            let palette = extract_palette(img, self.palette_size);
+            debug!("Extracted {} dominant colors", palette.len());
+            palette
+        } else {
+            Vec::new()
+        };
 
+        let encoded: Vec<String> = loaded
+            .iter()
+            .map(|(_, data)| encode_base64(data, start))
+            .collect();
+        let base64_images: Vec<&str> = encoded.iter().map(String::as_str).collect();
+
+        // Classify first so the main call can use the category-specific prompt
+        let mut token_usage = TokenUsage::default();
+        let mut config = self.analysis_config.clone();
+        if self.two_stage && config.content_category.is_none() {
+            let (classification, usage) = self.classify(img).await?;
+            token_usage += usage;
+            info!(
+                "Classified image as {:?} (confidence: {})",
+                classification.category,
+                classification.confidence
+            );
+            if classification.confidence >= MIN_CLASSIFICATION_CONFIDENCE {
+                config.content_category = Some(classification.into_category());
+            }
+        }
+
+        if config.detect_objects && !self.provider.supports_grounding() {
+            warn!("The model does not support grounding, bounding boxes are not requested");
+            config.detect_objects = false;
+        }
+
+        // Create prompt, leaving the structure to the provider when it can enforce a schema
+        let native_schema = self.uses_native_schema();
+        let prompt = match &self.prompts {
+            Some(prompts) =>
+                ImagePrompt::from_registry(
+                    prompts,
+                    self.prompt_format.clone(),
+                    config.clone(),
+                    native_schema
+                )?,
+            None if native_schema => ImagePrompt::schema_guided(config.clone()),
+            None => ImagePrompt::with_config(self.prompt_format.clone(), config.clone()),
+        };
+        let template = if native_schema { "schema_guided" } else { self.prompt_format.template_name() };
+        let prompt_version = self.prompt_version(
+            prompt.version.clone(),
+            template,
+            config.content_category.as_ref(),
+            base64_images.len()
+        );
+        let example_prompt = prompt.to_string();
+        let mut prompt = prompt.with_palette(&palette).to_string();
+        if base64_images.len() > 1 {
+            prompt = format!(
+                "The request contains {} images of the same subject, in order. Combine what they show into a single analysis.\n\n{}",
+                base64_images.len(),
+                prompt
+            );
+        }
+        debug!(
+            "Using prompt format: {:?}, native schema: {}, prompt version: {}",
+            self.prompt_format,
+            native_schema,
+            prompt_version
+        );
+
+        // Analyze with AI provider
+        let schema = native_schema.then(|| json_schema(&config));
+        let (analysis, usage) = self.send_with_examples(
+            template,
+            config.content_category.as_ref(),
+            &base64_images,
+            &example_prompt,
+            &prompt,
+            schema.as_ref()
+        ).await?;
+        token_usage += usage.unwrap_or_default();
+
+        // Repair JSON output, asking the model to continue if it was cut off
+        let (analysis, repairs) = if matches!(self.prompt_format, PromptFormat::Json) {
+            self.repair(&base64_images, &prompt, analysis, &mut token_usage).await?
+        } else {
+            (analysis, RepairReport::default())
+        };
+        info!(
+            "Total image processing completed, total_duration_ms: {}",
+            start.elapsed().as_millis()
+        );
+
+        let conversation = Conversation::new(encoded, &prompt, &analysis);
+        Ok(AnalysisOutput {
+            analysis,
+            token_usage,
+            repairs,
+            category: config.content_category,
+            image_width: img.width(),
+            image_height: img.height(),
+            prompt_version,
+            conversation,
+        })
+    }
+
+    /// Decodes the image and applies preprocessing, returning the decoded image and the
+    /// bytes to send; the original bytes are only sent when untouched.
+    fn load<'a>(&self, image_data: &'a [u8]) -> Result<(DynamicImage, Cow<'a, [u8]>), ProcessorError> {
         // Try to determine image format
         let format = image::guess_format(image_data).map_err(|e| {
             error!("Failed to guess image format: {}", e);
@@ -45,29 +429,320 @@ impl ImageProcessor {
         })?;
         debug!("Successfully loaded image: {}x{}", img.width(), img.height());
 
-        // Process image
-        debug!("Image enhancement complete");
+        // Apply crop and redactions
+        if self.preprocessing.is_empty() {
+            Ok((img, Cow::Borrowed(image_data)))
+        } else {
+            let processed = self.preprocessing.apply(&img)?;
+            let mut encoded = Cursor::new(Vec::new());
+            processed.write_to(&mut encoded, image::ImageOutputFormat::Png)?;
+            debug!(
+                "Preprocessed image: {}x{}, {} redactions",
+                processed.width(),
+                processed.height(),
+                self.preprocessing.redact.len()
+            );
+            Ok((processed, Cow::Owned(encoded.into_inner())))
+        }
+    }
+
+    async fn classify(
+        &self,
+        img: &DynamicImage
+    ) -> Result<(CategoryClassification, TokenUsage), ProcessorError> {
+        let thumbnail = img.thumbnail(CLASSIFICATION_IMAGE_SIZE, CLASSIFICATION_IMAGE_SIZE);
+        let mut encoded = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(thumbnail.to_rgb8()).write_to(
+            &mut encoded,
+            image::ImageOutputFormat::Jpeg(85)
+        )?;
+        let base64_data = base64::engine::general_purpose::STANDARD.encode(encoded.into_inner());
 
-        // Convert to base64
-        let mut base64_data = String::with_capacity((image_data.len() * 4) / 3 + 4);
-        base64::engine::general_purpose::STANDARD.encode_string(image_data, &mut base64_data);
-        info!(
-            "Base64 encoding completed, duration_ms: {}, bytes: {}",
-            start.elapsed().as_millis(),
-            image_data.len()
+        let provider = self.classifier.as_deref().unwrap_or(self.provider.as_ref());
+        let prompt = classification_prompt();
+        let (output, usage) = if self.native_schema && provider.supports_json_schema() {
+            provider.analyze_with_schema(&[&base64_data], &prompt, &classification_schema()).await?
+        } else {
+            provider.analyze(&[&base64_data], &prompt).await?
+        };
+
+        let (json, _) = repair_json(&output);
+        let classification = serde_json::from_str(&json).map_err(|e| {
+            ProcessorError::ResponseParseError(
+                format!("Failed to parse classification: {}. Response text: {}", e, output)
+            )
+        })?;
+        Ok((classification, usage.unwrap_or_default()))
+    }
+
+    async fn repair(
+        &self,
+        base64_images: &[&str],
+        prompt: &str,
+        mut raw: String,
+        token_usage: &mut TokenUsage
+    ) -> Result<(String, RepairReport), ProcessorError> {
+        let mut continuations = 0;
+        let (repaired, mut report) = loop {
+            let (repaired, report) = repair_json(&raw);
+            if !report.truncated || continuations >= self.max_continuations {
+                break (repaired, report);
+            }
+
+            continuations += 1;
+            debug!("Analysis was truncated, requesting continuation {}", continuations);
+            let tail_start = raw
+                .char_indices()
+                .rev()
+                .nth(CONTINUATION_CONTEXT_CHARS)
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            let continuation_prompt = format!(
+                "{}\n\nYour previous response was cut off. It ended with:\n{}\n\nContinue from exactly that point. Output only the remaining JSON, without repeating anything.",
+                prompt,
+                &raw[tail_start..]
+            );
+            let (continuation, usage) = self.provider.analyze(
+                base64_images,
+                &continuation_prompt
+            ).await?;
+            if let Some(usage) = usage {
+                *token_usage += usage;
+            }
+            raw.push_str(strip_code_fence(&continuation));
+        };
+
+        report.continuations = continuations;
+        if !report.is_clean() {
+            warn!("Repaired analysis output: {:?}", report);
+        }
+        Ok((repaired, report))
+    }
+
+    /// Writes alt text, limited to `AnalysisConfig::alt_text_limit` characters, and a
+    /// long description, and flags text in the image and low-contrast elements.
+    pub async fn describe_accessibility(
+        &self,
+        image_data: &[u8]
+    ) -> Result<(AccessibilityDescription, TokenUsage), ProcessorError> {
+        let start = Instant::now();
+        let (_, image_data) = self.load(image_data)?;
+        let base64_data = encode_base64(&image_data, start);
+
+        let limit = self.analysis_config.alt_text_limit;
+        let (prompt, prompt_version) = self.mode_prompt(
+            "accessibility",
+            accessibility::prompt(limit),
+            TemplateContext::new().with_variable("alt_text_limit", limit.to_string())
+        )?;
+        let prompt_version = self.prompt_version(
+            prompt_version,
+            "accessibility",
+            self.analysis_config.content_category.as_ref(),
+            1
+        );
+        let schema = (self.native_schema && self.provider.supports_json_schema()).then(
+            accessibility::schema
+        );
+        let (output, usage) = self.send(
+            "accessibility",
+            self.analysis_config.content_category.as_ref(),
+            &[&base64_data],
+            &prompt,
+            schema.as_ref()
+        ).await?;
+
+        let mut description = AccessibilityDescription::parse(&output, limit)?;
+        description.prompt_version = prompt_version;
+        if description.alt_text_shortened {
+            warn!("Alt text exceeded {} characters and was shortened", limit);
+        }
+        Ok((description, usage.unwrap_or_default()))
+    }
+
+    /// Transcribes the text of the image word by word with bounding boxes, grouped into
+    /// lines and paragraphs in reading order.
+    pub async fn extract_text(
+        &self,
+        image_data: &[u8]
+    ) -> Result<(TextLayout, TokenUsage), ProcessorError> {
+        let start = Instant::now();
+        let (img, image_data) = self.load(image_data)?;
+        let base64_data = encode_base64(&image_data, start);
+
+        let (prompt, prompt_version) = self.mode_prompt(
+            "ocr",
+            ocr::PROMPT.to_string(),
+            TemplateContext::new()
+        )?;
+        let prompt_version = self.prompt_version(
+            prompt_version,
+            "ocr",
+            self.analysis_config.content_category.as_ref(),
+            1
         );
+        let schema = (self.native_schema && self.provider.supports_json_schema()).then(ocr::schema);
+        let (output, usage) = self.send(
+            "ocr",
+            self.analysis_config.content_category.as_ref(),
+            &[&base64_data],
+            &prompt,
+            schema.as_ref()
+        ).await?;
 
-        // Create prompt
-        let prompt = ImagePrompt::new(self.prompt_format.clone()).to_string();
-        debug!("Using prompt format: {:?}", self.prompt_format);
+        let mut layout = TextLayout::parse(&output, img.width(), img.height())?;
+        layout.prompt_version = prompt_version;
+        debug!("Extracted {} words in {} paragraphs", layout.words().count(), layout.paragraphs.len());
+        Ok((layout, usage.unwrap_or_default()))
+    }
 
-        // Analyze with AI provider
-        let (analysis, token_usage) = self.provider.analyze(&base64_data, &prompt).await?;
-        info!(
-            "Total image processing completed, total_duration_ms: {}",
-            start.elapsed().as_millis()
+    /// Answers a question about the image in the form of `answer_type`, with the
+    /// model's confidence. The answer is null when the image does not show it.
+    ///
+    /// JSON answers are checked against the caller's schema locally, as it may not meet
+    /// the restrictions of strict provider-side schemas.
+    pub async fn answer_question(
+        &self,
+        image_data: &[u8],
+        question: &str,
+        answer_type: &AnswerType
+    ) -> Result<(Answer, TokenUsage), ProcessorError> {
+        if question.trim().is_empty() {
+            return Err(ProcessorError::InvalidQuestion("The question is empty".to_string()));
+        }
+        let start = Instant::now();
+        let (_, image_data) = self.load(image_data)?;
+        let base64_data = encode_base64(&image_data, start);
+
+        let (prompt, prompt_version) = self.mode_prompt(
+            "question",
+            questions::prompt(question, answer_type),
+            TemplateContext::new()
+                .with_variable("question", question.trim())
+                .with_variable("answer_format", answer_type.description())
+        )?;
+        let prompt_version = self.prompt_version(
+            prompt_version,
+            "question",
+            self.analysis_config.content_category.as_ref(),
+            1
         );
+        let schema = (
+            self.native_schema &&
+            answer_type.is_strict() &&
+            self.provider.supports_json_schema()
+        ).then(|| questions::schema(answer_type));
+        let (output, usage) = self.send(
+            "question",
+            self.analysis_config.content_category.as_ref(),
+            &[&base64_data],
+            &prompt,
+            schema.as_ref()
+        ).await?;
 
-        Ok((analysis, token_usage.unwrap_or_default()))
+        let mut answer = Answer::parse(&output, answer_type)?;
+        answer.prompt_version = prompt_version;
+        Ok((answer, usage.unwrap_or_default()))
     }
+
+    /// Answers a follow-up question about an analyzed image, resending the image and
+    /// earlier turns. The question and answer are added to the conversation.
+    pub async fn ask(
+        &self,
+        conversation: &mut Conversation,
+        question: &str
+    ) -> Result<(String, TokenUsage), ProcessorError> {
+        let question = question.trim();
+        if question.is_empty() {
+            return Err(ProcessorError::InvalidQuestion("The question is empty".to_string()));
+        }
+        if conversation.questions() >= MAX_QUESTIONS {
+            return Err(
+                ProcessorError::InvalidQuestion(
+                    format!("At most {} follow-up questions can be asked per analysis", MAX_QUESTIONS)
+                )
+            );
+        }
+
+        let mut messages = conversation.messages.clone();
+        messages.push(
+            Message::user(&format!("{}\n\n{}", FOLLOW_UP_INSTRUCTIONS, question), Vec::new())
+        );
+        let (answer, usage) = self.provider.chat(&messages, None).await?;
+        let answer = answer.trim().to_string();
+
+        conversation.messages.push(Message::user(question, Vec::new()));
+        conversation.messages.push(Message::assistant(&answer));
+        debug!("Answered follow-up question {}", conversation.questions());
+        Ok((answer, usage.unwrap_or_default()))
+    }
+
+    /// Describes the differences between a before and an after image, most significant
+    /// first. Preprocessing applies to both images.
+    pub async fn compare(
+        &self,
+        before: &[u8],
+        after: &[u8]
+    ) -> Result<(Comparison, TokenUsage), ProcessorError> {
+        let start = Instant::now();
+        let (_, before) = self.load(before)?;
+        let (_, after) = self.load(after)?;
+        let before = encode_base64(&before, start);
+        let after = encode_base64(&after, start);
+
+        let (prompt, prompt_version) = self.mode_prompt(
+            "comparison",
+            comparison::PROMPT.to_string(),
+            TemplateContext::new()
+        )?;
+        let schema = (self.native_schema && self.provider.supports_json_schema()).then(
+            comparison::schema
+        );
+        let (output, usage) = self.send(
+            "comparison",
+            self.analysis_config.content_category.as_ref(),
+            &[&before, &after],
+            &prompt,
+            schema.as_ref()
+        ).await?;
+
+        let mut comparison = Comparison::parse(&output)?;
+        comparison.prompt_version = prompt_version;
+        debug!("Found {} differences", comparison.differences.len());
+        Ok((comparison, usage.unwrap_or_default()))
+    }
+
+    /// Processes the image and parses the output into a validated [`AnalysisResult`].
+    ///
+    /// Only available for `PromptFormat::Json`, the other formats return free text.
+    pub async fn process_structured(
+        &self,
+        image_data: &[u8]
+    ) -> Result<(AnalysisResult, TokenUsage), ProcessorError> {
+        if !matches!(self.prompt_format, PromptFormat::Json) {
+            return Err(
+                ProcessorError::ConfigError(
+                    format!("Structured results require the JSON format, got {:?}", self.prompt_format)
+                )
+            );
+        }
+
+        let (analysis, token_usage) = self.process(image_data).await?;
+        let result = AnalysisResult::parse(&analysis).map_err(|e| {
+            error!("Failed to parse analysis: {}", e);
+            e
+        })?;
+        Ok((result, token_usage))
+    }
+}
+
+fn encode_base64(image_data: &[u8], start: Instant) -> String {
+    let mut base64_data = String::with_capacity((image_data.len() * 4) / 3 + 4);
+    base64::engine::general_purpose::STANDARD.encode_string(image_data, &mut base64_data);
+    info!(
+        "Base64 encoding completed, duration_ms: {}, bytes: {}",
+        start.elapsed().as_millis(),
+        image_data.len()
+    );
+    base64_data
 }
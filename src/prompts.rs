@@ -1,3 +1,13 @@
+use crate::{
+    accessibility::DEFAULT_ALT_TEXT_LIMIT,
+    categories,
+    errors::ProcessorError,
+    extraction,
+    language::{ self, DEFAULT_TRANSLATION_LANGUAGE },
+    tables,
+    templates::{ PromptRegistry, TemplateContext, BUILTIN_VERSION },
+    utils::DominantColor,
+};
 use serde::{ Deserialize, Serialize };
 use std::fmt;
 
@@ -6,14 +16,17 @@ pub struct ImagePrompt {
     pub text: String,
     pub format: PromptFormat,
     pub config: AnalysisConfig,
+    /// `builtin`, or the `name@version` of the template the text was rendered from
+    pub version: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum PromptFormat {
     Concise,
     Detailed,
-    Json,
+    #[default]
+    Json, // Default to JSON for structured output
     List,
     CategorySpecific(String),
     Custom(Vec<String>),
@@ -95,6 +108,7 @@ pub enum ContentCategory {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AnalysisConfig {
     pub extract_text: bool,
     pub detect_faces: bool,
@@ -110,16 +124,23 @@ pub struct AnalysisConfig {
     pub cultural_analysis: bool,
     pub technical_details: bool,
     pub accessibility_analysis: bool,
+    /// Reproduce tables cell by cell under `extracted_data.tables`. Off by default.
+    pub extract_tables: bool,
+    /// Give every main element a label and a normalized bounding box. Off by default, and
+    /// only requested from providers that support grounding.
+    pub detect_objects: bool,
+    /// Maximum length of generated alt text, in characters
+    pub alt_text_limit: usize,
+    /// Language to write the analysis in, as an ISO 639-1 code such as `es` or a name
+    /// such as `German`; the model picks one when unset.
+    pub language: Option<String>,
+    /// Keep extracted text in its original language, adding its detected language and
+    /// a translation into `language`, or English when unset. Off by default.
+    pub translate_text: bool,
     pub content_category: Option<ContentCategory>,
     pub custom_traits: Vec<String>,
 }
 
-impl Default for PromptFormat {
-    fn default() -> Self {
-        Self::Json // Default to JSON for structured output
-    }
-}
-
 impl Default for AnalysisConfig {
     fn default() -> Self {
         Self {
@@ -137,22 +158,391 @@ impl Default for AnalysisConfig {
             cultural_analysis: true,
             technical_details: true,
             accessibility_analysis: true,
+            extract_tables: false,
+            detect_objects: false,
+            alt_text_limit: DEFAULT_ALT_TEXT_LIMIT,
+            language: None,
+            translate_text: false,
             content_category: None,
             custom_traits: Vec::new(),
         }
     }
 }
 
+impl ContentCategory {
+    /// Human-readable name used when referring to the category in prompts.
+    pub fn label(&self) -> String {
+        match self {
+            Self::Screenshot { platform: Some(platform) } => format!("{} screenshot", platform),
+            Self::Discovered { name, .. } => name.clone(),
+            other =>
+                match serde_json::to_value(other) {
+                    Ok(serde_json::Value::String(name)) => name.replace('_', " "),
+                    Ok(serde_json::Value::Object(map)) =>
+                        map
+                            .keys()
+                            .next()
+                            .map(|k| k.replace('_', " "))
+                            .unwrap_or_default(),
+                    _ => String::new(),
+                }
+        }
+    }
+}
+
+impl PromptFormat {
+    /// Names returned by [`PromptFormat::template_name`].
+    pub const NAMES: [&'static str; 8] = [
+        "concise",
+        "detailed",
+        "json",
+        "list",
+        "category_specific",
+        "custom",
+        "discovery",
+        "platform_specific",
+    ];
+
+    /// Name of the registry template that replaces the built-in prompt for this format.
+    pub fn template_name(&self) -> &'static str {
+        match self {
+            Self::Concise => "concise",
+            Self::Detailed => "detailed",
+            Self::Json => "json",
+            Self::List => "list",
+            Self::CategorySpecific(_) => "category_specific",
+            Self::Custom(_) => "custom",
+            Self::Discovery => "discovery",
+            Self::PlatformSpecific(_) => "platform_specific",
+        }
+    }
+}
+
+impl ContentCategory {
+    /// Looks up a fixed category by its snake_case name, e.g. `business_card`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase().replace([' ', '-'], "_");
+        if name == "screenshot" {
+            return Some(Self::Screenshot { platform: None });
+        }
+        serde_json::from_value(serde_json::Value::String(name)).ok()
+    }
+}
+
+/// An `AnalysisConfig` toggle: its field name, getter, setter and, for toggles that are
+/// on by default, what free-form prompts leave out when it is switched off.
+type Toggle = (&'static str, fn(&AnalysisConfig) -> bool, fn(&mut AnalysisConfig, bool), Option<&'static str>);
+
+const TOGGLES: [Toggle; 17] = [
+    ("extract_text", |c| c.extract_text, |c, v| c.extract_text = v, Some("text transcription")),
+    ("detect_faces", |c| c.detect_faces, |c, v| c.detect_faces = v, Some("people and faces")),
+    ("identify_brands", |c| c.identify_brands, |c, v| c.identify_brands = v, Some("brands and logos")),
+    ("analyze_layout", |c| c.analyze_layout, |c, v| c.analyze_layout = v, Some("layout and composition")),
+    ("extract_data", |c| c.extract_data, |c, v| c.extract_data = v, Some("data points")),
+    ("color_analysis", |c| c.color_analysis, |c, v| c.color_analysis = v, Some("colors")),
+    (
+        "spatial_analysis",
+        |c| c.spatial_analysis,
+        |c, v| c.spatial_analysis = v,
+        Some("positions and spatial relationships"),
+    ),
+    ("semantic_analysis", |c| c.semantic_analysis, |c, v| c.semantic_analysis = v, Some("themes and meaning")),
+    ("detect_emotions", |c| c.detect_emotions, |c, v| c.detect_emotions = v, Some("emotional tone")),
+    ("identify_patterns", |c| c.identify_patterns, |c, v| c.identify_patterns = v, Some("recurring patterns")),
+    (
+        "historical_context",
+        |c| c.historical_context,
+        |c, v| c.historical_context = v,
+        Some("historical context"),
+    ),
+    (
+        "cultural_analysis",
+        |c| c.cultural_analysis,
+        |c, v| c.cultural_analysis = v,
+        Some("cultural context and symbolism"),
+    ),
+    ("technical_details", |c| c.technical_details, |c, v| c.technical_details = v, Some("technical quality")),
+    (
+        "accessibility_analysis",
+        |c| c.accessibility_analysis,
+        |c, v| c.accessibility_analysis = v,
+        Some("accessibility"),
+    ),
+    ("extract_tables", |c| c.extract_tables, |c, v| c.extract_tables = v, None),
+    ("detect_objects", |c| c.detect_objects, |c, v| c.detect_objects = v, None),
+    ("translate_text", |c| c.translate_text, |c, v| c.translate_text = v, None),
+];
+
+impl AnalysisConfig {
+    /// Names accepted by [`AnalysisConfig::set_toggle`].
+    pub fn toggle_names() -> impl Iterator<Item = &'static str> {
+        TOGGLES.iter().map(|(name, ..)| *name)
+    }
+
+    fn toggle(name: &str) -> Result<&'static Toggle, String> {
+        TOGGLES.iter()
+            .find(|(toggle, ..)| *toggle == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown analysis option {:?}, expected one of: {}",
+                    name,
+                    Self::toggle_names().collect::<Vec<_>>().join(", ")
+                )
+            })
+    }
+
+    /// Whether a toggle is enabled, by its field name.
+    pub fn enabled(&self, name: &str) -> Option<bool> {
+        Self::toggle(name)
+            .ok()
+            .map(|(_, get, ..)| get(self))
+    }
+
+    /// Enables or disables a toggle by its field name.
+    pub fn set_toggle(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let (_, _, set, _) = Self::toggle(name)?;
+        set(self, enabled);
+        Ok(())
+    }
+
+    /// Name of the output language for prompts, e.g. `Spanish` for `es`.
+    pub fn language_name(&self) -> Option<String> {
+        let language = self.language.as_deref()?.trim();
+        Some(language::display_name(language).unwrap_or_else(|_| language.to_string()))
+    }
+
+    /// Language extracted text is translated into, when translation is enabled.
+    fn translation_language(&self) -> Option<String> {
+        self.translate_text.then(|| {
+            self.language_name().unwrap_or_else(|| DEFAULT_TRANSLATION_LANGUAGE.to_string())
+        })
+    }
+
+    /// Instruction to write free text in the output language, for the dedicated modes.
+    pub fn language_instruction(&self) -> Option<String> {
+        self.language_name().map(|language| {
+            format!(
+                "Write all descriptions and explanations in {}. Keep JSON keys, and text quoted from the image, as they are.",
+                language
+            )
+        })
+    }
+
+    /// Extra instructions for free-form prompts; `None` when the defaults apply.
+    ///
+    /// Only the default-on toggles are listed when disabled; opt-in sections such as
+    /// tables add their own instructions when enabled.
+    fn guidance(&self) -> Option<String> {
+        let mut lines = Vec::new();
+        if let Some(category) = &self.content_category {
+            lines.push(format!("The image is a {}.", category.label()));
+            if let Some(focus) = categories::focus(category) {
+                lines.push(format!("Capture {}.", focus));
+            }
+            if let Some(instructions) = extraction::instructions(category) {
+                lines.push(instructions.to_string());
+            }
+        }
+        let skipped: Vec<_> = TOGGLES.iter()
+            .filter(|(_, get, ..)| !get(self))
+            .filter_map(|(.., description)| *description)
+            .collect();
+        if !skipped.is_empty() {
+            lines.push(format!("Do not describe: {}.", skipped.join(", ")));
+        }
+        if self.extract_tables {
+            lines.push(tables::INSTRUCTIONS.to_string());
+        }
+        if !self.custom_traits.is_empty() {
+            lines.push(format!("Also assess: {}.", self.custom_traits.join(", ")));
+        }
+        if let Some(language) = self.language_name() {
+            lines.push(format!("Write your response in {}.", language));
+        }
+        if let Some(target) = self.translation_language() {
+            lines.push(
+                format!(
+                    "Quote text shown in the image in its original language, followed by the language it is written in and its {} translation.",
+                    target
+                )
+            );
+        } else if self.language.is_some() && self.extract_text {
+            lines.push("Quote text shown in the image as written, without translating it.".to_string());
+        }
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+}
+
 impl ImagePrompt {
     pub fn new(format: PromptFormat) -> Self {
-        let text = match &format {
+        Self::with_config(format, AnalysisConfig::default())
+    }
+
+    /// Builds a prompt whose sections follow the enabled `AnalysisConfig` toggles.
+    pub fn with_config(format: PromptFormat, mut config: AnalysisConfig) -> Self {
+        if let PromptFormat::CategorySpecific(name) = &format {
+            if config.content_category.is_none() {
+                config.content_category = ContentCategory::from_name(name);
+            }
+        }
+
+        let mut text = match &format {
             PromptFormat::Concise => "Briefly describe what you see in this image.".to_string(),
             PromptFormat::Detailed =>
                 "Describe this image in detail, including all visual elements, colors, composition, and any notable features.".to_string(),
             PromptFormat::List =>
                 "List the main elements and features present in this image.".to_string(),
+            PromptFormat::Json => json_prompt(&config),
+            PromptFormat::CategorySpecific(category) =>
+                format!("Analyze this {} image with relevant domain-specific details.", category),
+            PromptFormat::Custom(traits) => {
+                format!("Analyze this image for the following aspects:\n{}", traits.join("\n- "))
+            }
+            PromptFormat::Discovery =>
+                "Discover and describe all interesting aspects of this image.".to_string(),
+            PromptFormat::PlatformSpecific(platform) =>
+                format!("Analyze this {} content with platform-specific considerations.", platform),
+        };
+
+        if !matches!(format, PromptFormat::Json) {
+            if let Some(guidance) = config.guidance() {
+                text = format!("{}\n\n{}", text, guidance);
+            }
+        }
+
+        Self {
+            text,
+            format,
+            config,
+            version: BUILTIN_VERSION.to_string(),
+        }
+    }
+
+    /// Builds a JSON prompt for providers that enforce [`json_schema`] natively,
+    /// leaving out the output structure and formatting rules.
+    pub fn schema_guided(config: AnalysisConfig) -> Self {
+        Self {
+            text: schema_guided_prompt(&config),
+            format: PromptFormat::Json,
+            config,
+            version: BUILTIN_VERSION.to_string(),
+        }
+    }
+
+    /// Renders the prompt from the registry's template for this format, or the
+    /// `schema_guided` template, falling back to the built-in prompt when there is none.
+    ///
+    /// Templates can use the variables `builtin` (the built-in prompt), `structure`,
+    /// `context`, `guidance`, `category`, `platform`, `traits`, `alt_text_limit` and
+    /// `language`, and test any `AnalysisConfig` toggle.
+    pub fn from_registry(
+        registry: &PromptRegistry,
+        format: PromptFormat,
+        config: AnalysisConfig,
+        schema_guided: bool
+    ) -> Result<Self, ProcessorError> {
+        let builtin = if schema_guided {
+            Self::schema_guided(config)
+        } else {
+            Self::with_config(format, config)
+        };
+        let name = if schema_guided { "schema_guided" } else { builtin.format.template_name() };
+        match registry.render(name, &builtin.template_context())? {
+            Some((text, version)) => Ok(Self { text, version, ..builtin }),
+            None => Ok(builtin),
+        }
+    }
+
+    fn template_context(&self) -> TemplateContext {
+        let config = &self.config;
+        let platform = match (&self.format, &config.content_category) {
+            (PromptFormat::PlatformSpecific(platform), _) => platform.clone(),
+            (_, Some(ContentCategory::Screenshot { platform: Some(platform) })) => platform.clone(),
+            _ => String::new(),
+        };
+        let traits = match &self.format {
+            PromptFormat::Custom(traits) => traits.join(", "),
+            _ => config.custom_traits.join(", "),
+        };
+        TemplateContext::new()
+            .with_config(config)
+            .with_variable("builtin", self.text.clone())
+            .with_variable("structure", render_structure(&json_structure(config)))
+            .with_variable("context", context_sections(config).join("\n\n"))
+            .with_variable("guidance", config.guidance().unwrap_or_default())
+            .with_variable(
+                "category",
+                config.content_category.as_ref().map(ContentCategory::label).unwrap_or_default()
+            )
+            .with_variable("platform", platform)
+            .with_variable("traits", traits)
+            .with_variable("alt_text_limit", config.alt_text_limit.to_string())
+            .with_variable("language", config.language_name().unwrap_or_default())
+    }
+
+    /// Appends locally measured colors so the model reports them instead of guessing.
+    pub fn with_palette(mut self, palette: &[DominantColor]) -> Self {
+        if palette.is_empty() {
+            return self;
+        }
+
+        let colors = palette
+            .iter()
+            .map(|c| format!("- {} (dominance: {:.2})", c.hex, c.dominance))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let instruction = match self.format {
             PromptFormat::Json =>
-                r#"SYSTEM INSTRUCTION - STRICT JSON OUTPUT REQUIRED
+                "Use exactly these hex codes and dominance values for analysis.visual.colors; only choose the color names.",
+            _ => "Use these values when describing the colors of the image.",
+        };
+        self.text = format!(
+            "{}\n\nMEASURED COLOR PALETTE (computed from the image pixels, treat as ground truth):\n{}\n{}",
+            self.text,
+            colors,
+            instruction
+        );
+        self
+    }
+
+    pub fn to_openai_content(&self) -> serde_json::Value {
+        serde_json::json!([
+            {
+                "type": "text",
+                "text": self.text
+            },
+            {
+                "type": "image_url",
+                "image_url": {
+                    "url": "data:image/jpeg;base64,{}" // Placeholder for base64 image
+                }
+            }
+        ])
+    }
+
+    pub fn to_ollama_prompt(&self) -> String {
+        self.text.clone()
+    }
+}
+
+impl fmt::Display for ImagePrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Shared JSON instructions, available to templates as partials.
+pub(crate) fn builtin_partials() -> [(&'static str, &'static str); 4] {
+    [
+        ("json_rules", JSON_RULES),
+        ("json_data_types", JSON_DATA_TYPES),
+        ("json_color_codes", JSON_COLOR_CODES),
+        ("json_reminder", JSON_REMINDER),
+    ]
+}
+
+const JSON_RULES: &str =
+    r#"SYSTEM INSTRUCTION - STRICT JSON OUTPUT REQUIRED
 ===================================================
 You are an image analysis system operating in STRICT JSON MODE. 
 YOU MUST FOLLOW THESE RULES WITHOUT EXCEPTION:
@@ -178,85 +568,13 @@ VALIDATION STEPS - You MUST:
 5. Ensure all strings use "quotes"
 6. Validate number formats
 7. Confirm no trailing commas
-8. Check for proper escaping
-
-REQUIRED OUTPUT STRUCTURE:
-{
-    "classification": {
-        "primary_category": "string",
-        "secondary_categories": ["string"],
-        "confidence": 0.0-1.0,
-        "discovered_categories": [{
-            "name": "string",
-            "confidence": 0.0-1.0,
-            "reasoning": "string"
-        }]
-    },
-    "content": {
-        "main_elements": [{
-            "type": "string",
-            "description": "string",
-            "location": "string",
-            "relationships": [{
-                "related_to": "string",
-                "type": "string"
-            }]
-        }],
-        "context": {
-            "setting": "string",
-            "purpose": "string",
-            "time_period": "string"
-        }
-    },
-    "analysis": {
-        "visual": {
-            "composition": {
-                "layout": "string",
-                "style": "string"
-            },
-            "colors": [{
-                "name": "string",
-                "hex": "string",
-                "dominance": 0.0-1.0
-            }]
-        },
-        "semantic": {
-            "themes": ["string"],
-            "emotional_tone": {
-                "primary": "string",
-                "confidence": 0.0-1.0
-            },
-            "symbolism": [{
-                "symbol": "string",
-                "meaning": "string"
-            }]
-        },
-        "technical": {
-            "quality": "string",
-            "creation_method": "string",
-            "notable_characteristics": ["string"]
-        }
-    },
-    "extracted_data": {
-        "text": [{
-            "content": "string",
-            "location": "string",
-            "purpose": "string"
-        }],
-        "data_points": [{
-            "type": "string",
-            "value": "string"
-        }]
-    },
-    "insights": {
-        "key_observations": ["string"],
-        "unusual_elements": ["string"],
-        "suggestions": ["string"]
-    },
-    "dynamic_extensions": {}
-}
+8. Check for proper escaping"#;
 
-DATA TYPE REQUIREMENTS:
+const COMPLETENESS: &str =
+    "COMPLETENESS:\n- Analyze the image completely and include ALL visible content\n- Do not omit or summarize any text or elements";
+
+const JSON_DATA_TYPES: &str =
+    r#"DATA TYPE REQUIREMENTS:
 - Strings: Must be valid UTF-8, properly escaped
 - Numbers: Must be valid JSON numbers
 - Arrays: Must be valid, even if empty []
@@ -268,15 +586,17 @@ CONFIDENCE SCORES:
 - MUST be between 0.0 and 1.0
 - MUST be decimal numbers
 - MUST NOT be strings
-- Examples: 0.95, 0.7, 0.32
+- Examples: 0.95, 0.7, 0.32"#;
 
-COLOR CODES:
+const JSON_COLOR_CODES: &str =
+    r#"COLOR CODES:
 - MUST be valid hex codes
 - MUST include # prefix
 - MUST be 6 characters after #
-- Example: #FF5733
+- Example: #FF5733"#;
 
-ARRAYS:
+const JSON_REMINDER: &str =
+    r#"ARRAYS:
 - MUST use [] brackets
 - MUST separate items with commas
 - MUST NOT have trailing comma
@@ -289,51 +609,317 @@ REMEMBER:
 4. NO human-readable explanations allowed
 5. ALL analysis must fit within this structure
 
-BEGIN ANALYSIS NOW WITH OPENING { AND END WITH CLOSING }"#.to_string(),
-            PromptFormat::CategorySpecific(category) =>
-                format!("Analyze this {} image with relevant domain-specific details.", category),
-            PromptFormat::Custom(traits) => {
-                format!("Analyze this image for the following aspects:\n{}", traits.join("\n- "))
-            }
-            PromptFormat::Discovery =>
-                "Discover and describe all interesting aspects of this image.".to_string(),
-            PromptFormat::PlatformSpecific(platform) =>
-                format!("Analyze this {} content with platform-specific considerations.", platform),
-        };
+BEGIN ANALYSIS NOW WITH OPENING { AND END WITH CLOSING }"#;
 
-        Self {
-            text,
-            format,
-            config: AnalysisConfig::default(),
+// Placeholders rendered unquoted in the structure shown to the model
+pub(crate) const CONFIDENCE: &str = "0.0-1.0";
+pub(crate) const BOOLEAN: &str = "true|false";
+pub(crate) const NUMBER: &str = "number";
+pub(crate) const NULLABLE_NUMBER: &str = "number|null";
+
+/// Builds the JSON output structure containing only the enabled sections.
+pub fn json_structure(config: &AnalysisConfig) -> serde_json::Value {
+    use serde_json::{ json, Map, Value };
+
+    let mut element = json!({ "type": "string", "description": "string" });
+    if config.detect_objects {
+        element["label"] = json!("string");
+        element["bbox"] = json!({ "x": NUMBER, "y": NUMBER, "width": NUMBER, "height": NUMBER });
+    }
+    if config.spatial_analysis {
+        element["location"] = json!("string");
+        element["relationships"] = json!([{ "related_to": "string", "type": "string" }]);
+    }
+
+    let mut content = Map::new();
+    content.insert("main_elements".into(), json!([element]));
+    if config.detect_faces {
+        let mut person = json!({ "description": "string" });
+        if config.detect_emotions {
+            person["expression"] = json!("string");
+        }
+        if config.spatial_analysis {
+            person["location"] = json!("string");
         }
+        content.insert("people".into(), json!([person]));
+    }
+    if config.identify_brands {
+        content.insert(
+            "brands".into(),
+            json!([{ "name": "string", "type": "string", "confidence": CONFIDENCE }])
+        );
+    }
+    let mut context = json!({ "setting": "string", "purpose": "string" });
+    if config.historical_context {
+        context["time_period"] = json!("string");
+    }
+    if config.cultural_analysis {
+        context["cultural_context"] = json!("string");
     }
+    content.insert("context".into(), context);
 
-    pub fn to_openai_content(&self) -> serde_json::Value {
-        serde_json::json!([
-            {
-                "type": "text",
-                "text": self.text
-            },
-            {
-                "type": "image_url",
-                "image_url": {
-                    "url": "data:image/jpeg;base64,{}" // Placeholder for base64 image
-                }
+    let mut analysis = Map::new();
+    let mut visual = Map::new();
+    if config.analyze_layout {
+        visual.insert("composition".into(), json!({ "layout": "string", "style": "string" }));
+    }
+    if config.color_analysis {
+        visual.insert(
+            "colors".into(),
+            json!([{ "name": "string", "hex": "string", "dominance": CONFIDENCE }])
+        );
+    }
+    if !visual.is_empty() {
+        analysis.insert("visual".into(), Value::Object(visual));
+    }
+    let mut semantic = Map::new();
+    if config.semantic_analysis {
+        semantic.insert("themes".into(), json!(["string"]));
+    }
+    if config.detect_emotions {
+        semantic.insert(
+            "emotional_tone".into(),
+            json!({ "primary": "string", "confidence": CONFIDENCE })
+        );
+    }
+    if config.cultural_analysis {
+        semantic.insert("symbolism".into(), json!([{ "symbol": "string", "meaning": "string" }]));
+    }
+    if !semantic.is_empty() {
+        analysis.insert("semantic".into(), Value::Object(semantic));
+    }
+    if config.technical_details {
+        analysis.insert(
+            "technical".into(),
+            json!({
+                "quality": "string",
+                "creation_method": "string",
+                "notable_characteristics": ["string"]
+            })
+        );
+    }
+    if config.accessibility_analysis {
+        analysis.insert(
+            "accessibility".into(),
+            json!({
+                "alt_text": "string",
+                "contains_text": BOOLEAN,
+                "contrast_issues": ["string"]
+            })
+        );
+    }
+
+    let mut extracted_data = Map::new();
+    if config.extract_text {
+        extracted_data.insert(
+            "text".into(),
+            if config.translate_text {
+                json!([
+                    {
+                        "content": "string",
+                        "location": "string",
+                        "purpose": "string",
+                        "language": "string",
+                        "translation": "string"
+                    }
+                ])
+            } else {
+                json!([{ "content": "string", "location": "string", "purpose": "string" }])
             }
-        ])
+        );
+    }
+    if config.extract_data {
+        extracted_data.insert("data_points".into(), json!([{ "type": "string", "value": "string" }]));
+    }
+    if config.extract_tables {
+        extracted_data.insert("tables".into(), tables::structure());
     }
 
-    pub fn to_ollama_prompt(&self) -> String {
-        self.text.clone()
+    let mut insights = json!({
+        "key_observations": ["string"],
+        "unusual_elements": ["string"],
+        "suggestions": ["string"]
+    });
+    if config.identify_patterns {
+        insights["patterns"] = json!(["string"]);
     }
+
+    let dynamic_extensions: Map<String, Value> = config.custom_traits
+        .iter()
+        .map(|t| (t.clone(), json!("string")))
+        .collect();
+
+    let mut root = Map::new();
+    root.insert(
+        "classification".into(),
+        json!({
+            "primary_category": "string",
+            "secondary_categories": ["string"],
+            "confidence": CONFIDENCE,
+            "discovered_categories": [{
+                "name": "string",
+                "confidence": CONFIDENCE,
+                "reasoning": "string"
+            }]
+        })
+    );
+    root.insert("content".into(), Value::Object(content));
+    if !analysis.is_empty() {
+        root.insert("analysis".into(), Value::Object(analysis));
+    }
+    if !extracted_data.is_empty() {
+        root.insert("extracted_data".into(), Value::Object(extracted_data));
+    }
+    root.insert("insights".into(), insights);
+    if let Some(details) = config.content_category.as_ref().and_then(categories::details_structure) {
+        root.insert("category_details".into(), details);
+    }
+    root.insert("dynamic_extensions".into(), Value::Object(dynamic_extensions));
+    Value::Object(root)
 }
 
-impl fmt::Display for ImagePrompt {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.text)
+fn render_structure(structure: &serde_json::Value) -> String {
+    use serde::Serialize;
+
+    let mut buf = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buf, formatter);
+    structure.serialize(&mut serializer).expect("serializing a JSON value cannot fail");
+    String::from_utf8(buf)
+        .expect("serde_json produces UTF-8")
+        .replace(&format!("\"{}\"", CONFIDENCE), CONFIDENCE)
+        .replace(&format!("\"{}\"", BOOLEAN), BOOLEAN)
+        .replace(&format!("\"{}\"", NUMBER), NUMBER)
+        .replace(&format!("\"{}\"", NULLABLE_NUMBER), NULLABLE_NUMBER)
+}
+
+/// Converts the structure from [`json_structure`] into a JSON Schema.
+///
+/// Every property is required and no others are allowed, as OpenAI's strict mode demands.
+pub fn json_schema(config: &AnalysisConfig) -> serde_json::Value {
+    schema_for(&json_structure(config))
+}
+
+fn schema_for(value: &serde_json::Value) -> serde_json::Value {
+    use serde_json::{ json, Value };
+
+    match value {
+        Value::String(s) if s == CONFIDENCE =>
+            json!({ "type": "number", "minimum": 0.0, "maximum": 1.0 }),
+        Value::String(s) if s == BOOLEAN => json!({ "type": "boolean" }),
+        Value::String(s) if s == NUMBER => json!({ "type": "number" }),
+        Value::String(s) if s == NULLABLE_NUMBER => json!({ "type": ["number", "null"] }),
+        Value::Array(items) =>
+            json!({
+                "type": "array",
+                "items": items.first().map(schema_for).unwrap_or_else(|| json!({ "type": "string" }))
+            }),
+        Value::Object(map) => {
+            let properties: serde_json::Map<String, Value> = map
+                .iter()
+                .map(|(k, v)| (k.clone(), schema_for(v)))
+                .collect();
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": map.keys().collect::<Vec<_>>(),
+                "additionalProperties": false
+            })
+        }
+        _ => json!({ "type": "string" }),
     }
 }
 
+/// Short instructions used when the provider enforces the schema itself.
+fn schema_guided_prompt(config: &AnalysisConfig) -> String {
+    let mut sections = vec![
+        "Analyze this image thoroughly and respond with JSON matching the provided schema. Capture all visible text and elements without omitting or summarizing any. Use 0.0-1.0 for confidence and dominance values and #RRGGBB for colors. Use empty arrays or strings when something does not apply.".to_string()
+    ];
+    sections.extend(context_sections(config));
+    sections.join("\n\n")
+}
+
+fn context_sections(config: &AnalysisConfig) -> Vec<String> {
+    let mut sections = Vec::new();
+    if let Some(category) = &config.content_category {
+        let mut section = format!(
+            "KNOWN CONTENT CATEGORY:\n- The image is a {}\n- Use it as classification.primary_category",
+            category.label()
+        );
+        if let Some(focus) = categories::focus(category) {
+            section.push_str(&format!("\n- Capture {}", focus));
+        }
+        if categories::details_structure(category).is_some() {
+            section.push_str("\n- Fill category_details with the category-specific fields");
+        }
+        if let Some(instructions) = extraction::instructions(category) {
+            section.push_str(&format!("\n- {}", instructions));
+        }
+        sections.push(section);
+    }
+    if config.accessibility_analysis {
+        sections.push(
+            format!(
+                "ACCESSIBILITY:\n- Keep alt_text under {} characters and describe content and purpose, not appearance\n- List text or elements with low contrast against their background under contrast_issues",
+                config.alt_text_limit
+            )
+        );
+    }
+    if config.detect_objects {
+        sections.push(
+            "OBJECT DETECTION:\n- List every distinct object under content.main_elements with a short lowercase singular label such as \"dog\" or \"traffic light\"\n- Give x, y, width and height of each bbox as fractions of the image width and height between 0 and 1, tight around the visible object".to_string()
+        );
+    }
+    if config.extract_tables {
+        sections.push(format!("TABLES:\n- {}", tables::INSTRUCTIONS));
+    }
+    if let Some(language) = config.language_name() {
+        let mut section = format!(
+            "OUTPUT LANGUAGE:\n- Write every description, summary and name in {}\n- Keep JSON keys, hex codes and BOOLEAN values as specified",
+            language
+        );
+        if config.extract_text {
+            section.push_str(
+                "\n- Copy text under extracted_data.text exactly as written in the image, without translating it"
+            );
+        }
+        sections.push(section);
+    }
+    if let (Some(target), true) = (config.translation_language(), config.extract_text) {
+        sections.push(
+            format!(
+                "TRANSLATION:\n- Give each run of text in a single language its own item under extracted_data.text, keeping content in the original language\n- Set language to the ISO 639-1 code of the language content is written in, such as \"ja\"\n- Set translation to content translated into {}, or to content unchanged when it is already in {}",
+                target,
+                target
+            )
+        );
+    }
+    if !config.custom_traits.is_empty() {
+        sections.push(
+            format!(
+                "CUSTOM TRAITS:\n- Assess each of these in dynamic_extensions: {}",
+                config.custom_traits.join(", ")
+            )
+        );
+    }
+    sections
+}
+
+fn json_prompt(config: &AnalysisConfig) -> String {
+    let mut sections = vec![
+        JSON_RULES.to_string(),
+        format!("REQUIRED OUTPUT STRUCTURE:\n{}", render_structure(&json_structure(config))),
+        COMPLETENESS.to_string()
+    ];
+    sections.extend(context_sections(config));
+    sections.push(JSON_DATA_TYPES.to_string());
+    if config.color_analysis {
+        sections.push(JSON_COLOR_CODES.to_string());
+    }
+    sections.push(JSON_REMINDER.to_string());
+    sections.join("\n\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -345,6 +931,13 @@ mod tests {
         assert!(prompt.text.contains("Describe this image in detail"));
     }
 
+    #[test]
+    fn test_only_analysis_prompts_ask_for_all_content() {
+        assert!(ImagePrompt::new(PromptFormat::Json).text.contains(COMPLETENESS));
+        assert!(ImagePrompt::schema_guided(AnalysisConfig::default()).text.contains("without omitting"));
+        assert!(!ImagePrompt::new(PromptFormat::Concise).text.contains("omit"));
+    }
+
     #[test]
     fn test_prompt_serialization() {
         let format = PromptFormat::Concise;
@@ -360,6 +953,17 @@ mod tests {
         assert!(prompt.text.contains("Analyze this product image"));
     }
 
+    #[test]
+    fn test_category_specific_uses_content_category() {
+        let prompt = ImagePrompt::new(PromptFormat::CategorySpecific("receipt".to_string()));
+        assert!(matches!(prompt.config.content_category, Some(ContentCategory::Receipt)));
+        assert!(prompt.text.contains("Analyze this receipt image"));
+        assert!(prompt.text.contains("line item"));
+
+        let structure = json_structure(&prompt.config);
+        assert!(structure["category_details"]["line_items"].is_array());
+    }
+
     #[test]
     fn test_custom_traits() {
         let format = PromptFormat::Custom(
@@ -383,4 +987,147 @@ mod tests {
         let prompt = ImagePrompt::new(format);
         assert!(prompt.text.contains("instagram"));
     }
+
+    #[test]
+    fn test_config_disables_json_sections() {
+        let config = AnalysisConfig {
+            detect_emotions: false,
+            cultural_analysis: false,
+            ..Default::default()
+        };
+        let prompt = ImagePrompt::with_config(PromptFormat::Json, config);
+        assert!(!prompt.text.contains("emotional_tone"));
+        assert!(!prompt.text.contains("symbolism"));
+        assert!(prompt.text.contains("\"themes\""));
+        assert!(prompt.text.contains("\"confidence\": 0.0-1.0"));
+    }
+
+    #[test]
+    fn test_every_toggle_can_be_set_by_name() {
+        let mut config = AnalysisConfig::default();
+        for name in AnalysisConfig::toggle_names() {
+            let enabled = config.enabled(name).unwrap();
+            config.set_toggle(name, !enabled).unwrap();
+            assert_eq!(config.enabled(name), Some(!enabled), "{}", name);
+        }
+        assert!(config.translate_text && !config.extract_text);
+        assert_eq!(config.enabled("unknown"), None);
+    }
+
+    #[test]
+    fn test_config_guidance_for_text_formats() {
+        let mut config = AnalysisConfig {
+            content_category: Some(ContentCategory::Receipt),
+            custom_traits: vec!["brand_safety".to_string()],
+            ..Default::default()
+        };
+        config.set_toggle("detect_faces", false).unwrap();
+        assert!(config.set_toggle("unknown", false).is_err());
+
+        let prompt = ImagePrompt::with_config(PromptFormat::Concise, config);
+        assert!(prompt.text.contains("The image is a receipt."));
+        assert!(prompt.text.contains("Do not describe: people and faces."));
+        assert!(prompt.text.contains("Also assess: brand_safety."));
+    }
+
+    #[test]
+    fn test_json_schema_follows_config() {
+        let config = AnalysisConfig {
+            detect_emotions: false,
+            custom_traits: vec!["brand_safety".to_string()],
+            ..Default::default()
+        };
+        let schema = json_schema(&config);
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(
+            schema["properties"]["classification"]["properties"]["confidence"]["type"],
+            "number"
+        );
+        let semantic = &schema["properties"]["analysis"]["properties"]["semantic"];
+        assert!(semantic["properties"].get("emotional_tone").is_none());
+        assert_eq!(
+            schema["properties"]["dynamic_extensions"]["required"],
+            serde_json::json!(["brand_safety"])
+        );
+
+        let prompt = ImagePrompt::schema_guided(config);
+        assert!(!prompt.text.contains("STRICT JSON MODE"));
+        assert!(prompt.text.contains("brand_safety"));
+    }
+
+    #[test]
+    fn test_tables_are_opt_in() {
+        let structure = json_structure(&AnalysisConfig::default());
+        assert!(structure["extracted_data"].get("tables").is_none());
+        assert!(!ImagePrompt::new(PromptFormat::Concise).text.contains("table"));
+
+        let config = AnalysisConfig {
+            extract_tables: true,
+            ..Default::default()
+        };
+        assert!(json_structure(&config)["extracted_data"]["tables"].is_array());
+        assert!(ImagePrompt::with_config(PromptFormat::Json, config).text.contains("TABLES:"));
+    }
+
+    #[test]
+    fn test_palette_injection() {
+        let palette = vec![DominantColor {
+            hex: "#FF5733".to_string(),
+            rgb: [255, 87, 51],
+            dominance: 0.6,
+        }];
+        let prompt = ImagePrompt::new(PromptFormat::Json).with_palette(&palette);
+        assert!(prompt.text.contains("#FF5733 (dominance: 0.60)"));
+        assert!(prompt.text.contains("analysis.visual.colors"));
+    }
+
+    #[test]
+    fn test_output_language_and_translation() {
+        let config = AnalysisConfig { language: Some("es".to_string()), ..Default::default() };
+        let prompt = ImagePrompt::with_config(PromptFormat::Json, config.clone());
+        assert!(prompt.text.contains("OUTPUT LANGUAGE:\n- Write every description, summary and name in Spanish"));
+        assert!(prompt.text.contains("without translating it"));
+        assert!(!prompt.text.contains("TRANSLATION:"));
+        assert!(json_structure(&config)["extracted_data"]["text"][0].get("translation").is_none());
+
+        let config = AnalysisConfig { translate_text: true, ..config };
+        let prompt = ImagePrompt::with_config(PromptFormat::Json, config.clone());
+        assert!(prompt.text.contains("content translated into Spanish"));
+        let text = &json_structure(&config)["extracted_data"]["text"][0];
+        assert_eq!(text["language"], "string");
+        assert_eq!(text["translation"], "string");
+
+        let config = AnalysisConfig { translate_text: true, ..Default::default() };
+        let prompt = ImagePrompt::with_config(PromptFormat::Concise, config);
+        assert!(prompt.text.contains("its English translation"));
+        assert!(!prompt.text.contains("Write your response in"));
+    }
+
+    #[test]
+    fn test_registry_templates_replace_builtin_prompts() {
+        let registry = PromptRegistry::from_toml(
+            r#"
+[templates.concise]
+version = "2"
+template = "{{builtin}}\n{{#if category}}Focus on the {{category}}.{{/if}}"
+"#
+        ).unwrap();
+        let config = AnalysisConfig {
+            content_category: Some(ContentCategory::Receipt),
+            ..Default::default()
+        };
+
+        let prompt = ImagePrompt::from_registry(&registry, PromptFormat::Concise, config, false).unwrap();
+        assert_eq!(prompt.version, "concise@2");
+        assert!(prompt.text.starts_with("Briefly describe"));
+        assert!(prompt.text.ends_with("Focus on the receipt."));
+
+        let prompt = ImagePrompt::from_registry(
+            &registry,
+            PromptFormat::Json,
+            AnalysisConfig::default(),
+            false
+        ).unwrap();
+        assert_eq!(prompt.version, BUILTIN_VERSION);
+    }
 }
@@ -5,7 +5,7 @@ use crate::errors::ProcessorError;
 use async_trait::async_trait;
 pub use ollama::OllamaProvider;
 pub use openai::OpenAIProvider;
-use serde::Serialize;
+use serde::{ Deserialize, Serialize };
 
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct TokenUsage {
@@ -14,17 +14,221 @@ pub struct TokenUsage {
     pub total_tokens: usize,
 }
 
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum AIProvider {
     OpenAI,
     Ollama,
 }
 
+impl AIProvider {
+    /// Names accepted by [`AIProvider::from_str`](std::str::FromStr).
+    pub const NAMES: [&'static str; 2] = ["openai", "ollama"];
+
+    /// Creates the client for this provider, using its default model when none is given.
+    pub fn create(self, model: Option<String>) -> Box<dyn Provider> {
+        match self {
+            AIProvider::OpenAI => Box::new(OpenAIProvider::new(model)),
+            AIProvider::Ollama => Box::new(OllamaProvider::new(model)),
+        }
+    }
+}
+
+impl std::str::FromStr for AIProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "openai" => Ok(Self::OpenAI),
+            "ollama" => Ok(Self::Ollama),
+            other =>
+                Err(format!("Unknown provider {:?}, expected one of: {}", other, Self::NAMES.join(", "))),
+        }
+    }
+}
+
+/// How closely an image is looked at, trading detail for input tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageDetail {
+    Low,
+    High,
+    Auto,
+}
+
+impl std::str::FromStr for ImageDetail {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "low" => Ok(Self::Low),
+            "high" => Ok(Self::High),
+            "auto" => Ok(Self::Auto),
+            other => Err(format!("Unknown image detail {:?}, expected one of: low, high, auto", other)),
+        }
+    }
+}
+
+/// Sampling and length settings sent with every request; unset values use the
+/// provider's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationParams {
+    /// From 0.0 to 2.0; OpenAI requests default to 0.0
+    pub temperature: Option<f32>,
+    /// Nucleus sampling probability mass, from 0.0 to 1.0
+    pub top_p: Option<f32>,
+    /// Maximum tokens to generate; OpenAI requests default to 16384
+    pub max_tokens: Option<u32>,
+    /// Makes sampling repeatable, on a best-effort basis for OpenAI
+    pub seed: Option<u64>,
+    /// Sequences that end generation, up to 4
+    pub stop: Vec<String>,
+    /// Resolution images are read at; only supported by OpenAI
+    pub image_detail: Option<ImageDetail>,
+}
+
+impl GenerationParams {
+    /// Maximum number of stop sequences, the OpenAI limit.
+    pub const MAX_STOP_SEQUENCES: usize = 4;
+
+    /// Checks that values are in the ranges providers accept.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(format!("temperature must be between 0.0 and 2.0, got {}", temperature));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                return Err(format!("top_p must be between 0.0 and 1.0, got {}", top_p));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be at least 1".to_string());
+        }
+        if self.stop.len() > Self::MAX_STOP_SEQUENCES {
+            return Err(
+                format!(
+                    "At most {} stop sequences are allowed, got {}",
+                    Self::MAX_STOP_SEQUENCES,
+                    self.stop.len()
+                )
+            );
+        }
+        if self.stop.iter().any(String::is_empty) {
+            return Err("Stop sequences must not be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Who a [`Message`] is from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// One turn of a conversation sent to a provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub role: Role,
+    pub text: String,
+    /// Base64-encoded images attached to the turn
+    pub images: Vec<String>,
+}
+
+impl Message {
+    pub fn user(text: &str, images: Vec<String>) -> Self {
+        Self { role: Role::User, text: text.to_string(), images }
+    }
+
+    pub fn assistant(text: &str) -> Self {
+        Self { role: Role::Assistant, text: text.to_string(), images: Vec::new() }
+    }
+}
+
 #[async_trait]
 pub trait Provider: Send + Sync {
+    /// Sends a conversation, such as few-shot examples followed by the image to analyze,
+    /// and returns the reply to its last user turn.
+    ///
+    /// Output is constrained to `schema` when [`Provider::supports_json_schema`] returns
+    /// true; other providers ignore it, so the prompt must describe the structure itself.
+    async fn chat(
+        &self,
+        messages: &[Message],
+        schema: Option<&serde_json::Value>,
+    ) -> Result<(String, Option<TokenUsage>), ProcessorError>;
+
+    /// Analyzes one or more images, attached in order to a single user turn.
     async fn analyze(
         &self,
-        base64_image: &str,
+        base64_images: &[&str],
         prompt: &str,
-    ) -> Result<(String, Option<TokenUsage>), ProcessorError>;
+    ) -> Result<(String, Option<TokenUsage>), ProcessorError> {
+        self.chat(&[Message::user(prompt, to_owned(base64_images))], None).await
+    }
+
+    /// Sets the sampling and length settings of later requests. Providers without such
+    /// settings ignore them.
+    fn set_generation_params(&mut self, _params: GenerationParams) {}
+
+    /// Whether the provider can constrain its output to a JSON Schema.
+    fn supports_json_schema(&self) -> bool {
+        false
+    }
+
+    /// Whether the model can locate objects with bounding boxes reliably enough to ask for them.
+    fn supports_grounding(&self) -> bool {
+        false
+    }
+
+    /// Analyzes the images with output constrained to `schema`.
+    async fn analyze_with_schema(
+        &self,
+        base64_images: &[&str],
+        prompt: &str,
+        schema: &serde_json::Value,
+    ) -> Result<(String, Option<TokenUsage>), ProcessorError> {
+        self.chat(&[Message::user(prompt, to_owned(base64_images))], Some(schema)).await
+    }
+}
+
+fn to_owned(base64_images: &[&str]) -> Vec<String> {
+    base64_images.iter().map(|image| image.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generation_params_validation() {
+        assert!(GenerationParams::default().validate().is_ok());
+        let params = GenerationParams {
+            temperature: Some(0.7),
+            top_p: Some(0.9),
+            max_tokens: Some(256),
+            seed: Some(42),
+            stop: vec!["END".to_string()],
+            image_detail: Some(ImageDetail::Low),
+        };
+        assert!(params.validate().is_ok());
+        assert!(GenerationParams { temperature: Some(2.5), ..Default::default() }.validate().is_err());
+        assert!(GenerationParams { top_p: Some(-0.1), ..Default::default() }.validate().is_err());
+        assert!(GenerationParams { max_tokens: Some(0), ..Default::default() }.validate().is_err());
+        let stop = vec!["a".to_string(); 5];
+        assert!(GenerationParams { stop, ..Default::default() }.validate().is_err());
+        assert_eq!("HIGH".parse::<ImageDetail>(), Ok(ImageDetail::High));
+        assert!("medium".parse::<ImageDetail>().is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use super::{Provider, TokenUsage};
+use super::{GenerationParams, Message, Provider, Role, TokenUsage};
 use crate::errors::ProcessorError;
 use async_trait::async_trait;
 use reqwest::Client;
@@ -7,18 +7,69 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize)]
 struct OllamaRequest {
     model: String,
-    prompt: String,
+    messages: Vec<OllamaMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "OllamaOptions::is_empty")]
+    options: OllamaOptions,
+}
+
+/// Model parameters of an Ollama request; unset values use the model's defaults.
+#[derive(Debug, Default, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+}
+
+impl OllamaOptions {
+    /// Ollama has no image detail setting, so `image_detail` is not passed on.
+    fn new(params: &GenerationParams) -> Self {
+        Self {
+            temperature: params.temperature,
+            top_p: params.top_p,
+            num_predict: params.max_tokens,
+            seed: params.seed,
+            stop: params.stop.clone(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.num_predict.is_none()
+            && self.seed.is_none()
+            && self.stop.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     images: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct OllamaResponse {
-    response: String,
+    message: OllamaMessage,
 }
 
+/// Model families trained to output object locations.
+const GROUNDING_MODELS: [&str; 4] = ["qwen2.5vl", "qwen3-vl", "moondream", "llama4"];
+
 pub struct OllamaProvider {
     client: Client,
     model: String,
+    params: GenerationParams,
 }
 
 impl OllamaProvider {
@@ -26,26 +77,61 @@ impl OllamaProvider {
         Self {
             client: Client::new(),
             model: model.unwrap_or_else(|| "moondream".to_string()),
+            params: GenerationParams::default(),
         }
     }
 }
 
 #[async_trait]
 impl Provider for OllamaProvider {
-    async fn analyze(
+    async fn chat(
+        &self,
+        messages: &[Message],
+        schema: Option<&serde_json::Value>,
+    ) -> Result<(String, Option<TokenUsage>), ProcessorError> {
+        self.generate(messages, schema.cloned()).await
+    }
+
+    fn set_generation_params(&mut self, params: GenerationParams) {
+        self.params = params;
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        true
+    }
+
+    fn supports_grounding(&self) -> bool {
+        GROUNDING_MODELS.iter().any(|name| self.model.starts_with(name))
+    }
+}
+
+impl OllamaProvider {
+    async fn generate(
         &self,
-        base64_image: &str,
-        prompt: &str,
+        messages: &[Message],
+        format: Option<serde_json::Value>,
     ) -> Result<(String, Option<TokenUsage>), ProcessorError> {
         let ollama_request = OllamaRequest {
             model: self.model.clone(),
-            prompt: prompt.to_string(),
-            images: vec![base64_image.to_string()],
+            messages: messages
+                .iter()
+                .map(|message| OllamaMessage {
+                    role: match message.role {
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                    }
+                    .to_string(),
+                    content: message.text.clone(),
+                    images: message.images.clone(),
+                })
+                .collect(),
+            format,
+            options: OllamaOptions::new(&self.params),
         };
 
         let response = self
             .client
-            .post("http://localhost:11434/api/generate")
+            .post("http://localhost:11434/api/chat")
             .json(&ollama_request)
             .send()
             .await?;
@@ -69,7 +155,7 @@ impl Provider for OllamaProvider {
         let mut full_response = String::new();
         for line in text.lines() {
             if let Ok(chunk) = serde_json::from_str::<OllamaResponse>(line) {
-                full_response.push_str(&chunk.response);
+                full_response.push_str(&chunk.message.content);
             }
         }
 
@@ -82,3 +168,22 @@ impl Provider for OllamaProvider {
         Ok((full_response, None))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generation_params_map_to_options() {
+        let options = OllamaOptions::new(&GenerationParams::default());
+        assert!(options.is_empty());
+
+        let params = GenerationParams {
+            max_tokens: Some(200),
+            seed: Some(7),
+            ..Default::default()
+        };
+        let options = serde_json::to_value(OllamaOptions::new(&params)).unwrap();
+        assert_eq!(options, serde_json::json!({ "num_predict": 200, "seed": 7 }));
+    }
+}
@@ -1,4 +1,4 @@
-use super::{ Provider, TokenUsage };
+use super::{ GenerationParams, Message, Provider, Role, TokenUsage };
 use crate::errors::ProcessorError;
 use async_trait::async_trait;
 use reqwest::Client;
@@ -31,58 +31,80 @@ struct OpenAIUsage {
 pub struct OpenAIProvider {
     client: Client,
     model: String,
-    temperature: f32,
+    params: GenerationParams,
 }
 
+/// Sampling temperature used when none is set, for repeatable analyses.
+const DEFAULT_TEMPERATURE: f32 = 0.0;
+
+/// Output token limit used when none is set.
+const DEFAULT_MAX_COMPLETION_TOKENS: u32 = 16384;
+
+/// Model families that accept a `json_schema` response format.
+const JSON_SCHEMA_MODELS: [&str; 6] = ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4-mini"];
+
+/// Snapshots of those families released before `json_schema` support.
+const PRE_JSON_SCHEMA_MODELS: [&str; 3] = ["gpt-4o-2024-05-13", "o1-mini", "o1-preview"];
+
+/// Reasoning model families, which only accept the default `temperature` and `top_p`.
+const REASONING_MODELS: [&str; 4] = ["o1", "o3", "o4-mini", "gpt-5"];
+
+/// Model families that locate objects reliably enough to ask for bounding boxes.
+const GROUNDING_MODELS: [&str; 4] = ["gpt-4.1", "gpt-5", "o3", "o4-mini"];
+
 impl OpenAIProvider {
     pub fn new(model: Option<String>) -> Self {
         Self {
             client: Client::new(),
             model: model.unwrap_or_else(|| "gpt-4o".to_string()),
-            temperature: 0.0,
+            params: GenerationParams::default(),
         }
     }
 }
 
 #[async_trait]
 impl Provider for OpenAIProvider {
-    async fn analyze(
+    async fn chat(
         &self,
-        base64_image: &str,
-        prompt: &str
+        messages: &[Message],
+        schema: Option<&serde_json::Value>
     ) -> Result<(String, Option<TokenUsage>), ProcessorError> {
-        let api_key = std::env::var("OPENAI_API_KEY").map_err(ProcessorError::EnvError)?;
-
-        let system_prompt =
-            "You are a detailed image analysis system. When analyzing images, please provide a complete and thorough analysis in a structured JSON format. Include all visible text, elements, and details. Never truncate or summarize the content - provide everything you can see in the image. If the content is long, break it into appropriate sections but ensure ALL content is captured.";
-
-        let request_body =
+        let response_format = schema.map(|schema| {
             json!({
-            "model": self.model,
-            "temperature": self.temperature,
-            "max_completion_tokens": 16384,
-            "messages": [
-                {
-                    "role": "system",
-                    "content": system_prompt
-                },
-                {
-                    "role": "user",
-                    "content": [
-                        {
-                            "type": "text",
-                            "text": format!("{}\nPlease analyze this image completely and provide ALL visible content in a structured JSON format. Do not omit or summarize any text or elements.", prompt)
-                        },
-                        {
-                            "type": "image_url",
-                            "image_url": {
-                                "url": format!("data:image/jpeg;base64,{}", base64_image)
-                            }
-                        }
-                    ]
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "image_analysis",
+                    "strict": true,
+                    "schema": schema
                 }
-            ]
+            })
         });
+        self.complete(messages, response_format).await
+    }
+
+    fn set_generation_params(&mut self, params: GenerationParams) {
+        self.params = params;
+    }
+
+    fn supports_json_schema(&self) -> bool {
+        JSON_SCHEMA_MODELS.iter().any(|name| self.model.starts_with(name))
+            && !PRE_JSON_SCHEMA_MODELS.iter().any(|name| self.model.starts_with(name))
+    }
+
+    fn supports_grounding(&self) -> bool {
+        GROUNDING_MODELS.iter().any(|name| self.model.starts_with(name))
+    }
+
+}
+
+impl OpenAIProvider {
+    async fn complete(
+        &self,
+        messages: &[Message],
+        response_format: Option<serde_json::Value>
+    ) -> Result<(String, Option<TokenUsage>), ProcessorError> {
+        let request_body = self.request_body(messages, response_format)?;
+        let api_key = std::env::var("OPENAI_API_KEY").map_err(ProcessorError::EnvError)?;
 
         let response = self.client
             .post("https://api.openai.com/v1/chat/completions")
@@ -130,4 +152,131 @@ impl Provider for OpenAIProvider {
 
         Ok((analysis, token_usage))
     }
+
+    /// Builds the chat completion request for the messages.
+    fn request_body(
+        &self,
+        messages: &[Message],
+        response_format: Option<serde_json::Value>
+    ) -> Result<serde_json::Value, ProcessorError> {
+        let system_prompt =
+            "You are a detailed image analysis system. When analyzing images, please provide a complete and thorough analysis in a structured JSON format. Include all visible text, elements, and details. Never truncate or summarize the content - provide everything you can see in the image. If the content is long, break it into appropriate sections but ensure ALL content is captured.";
+
+        let mut chat = vec![json!({ "role": "system", "content": system_prompt })];
+        for message in messages {
+            chat.push(match message.role {
+                // Follow-up questions without images are sent as asked
+                Role::User if message.images.is_empty() =>
+                    json!({ "role": "user", "content": message.text }),
+                Role::User => {
+                    let mut content = vec![
+                        json!({
+                            "type": "text",
+                            "text": message.text
+                        })
+                    ];
+                    content.extend(
+                        message.images.iter().map(|image| {
+                            let mut image_url =
+                                json!({
+                                "url": format!("data:image/jpeg;base64,{}", image)
+                            });
+                            if let Some(detail) = self.params.image_detail {
+                                image_url["detail"] = json!(detail);
+                            }
+                            json!({ "type": "image_url", "image_url": image_url })
+                        })
+                    );
+                    json!({ "role": "user", "content": content })
+                }
+                Role::Assistant => json!({ "role": "assistant", "content": message.text }),
+            });
+        }
+        let mut request_body =
+            json!({
+            "model": self.model,
+            "max_completion_tokens": self.params.max_tokens.unwrap_or(DEFAULT_MAX_COMPLETION_TOKENS),
+            "messages": chat
+        });
+        if self.is_reasoning_model() {
+            if self.params.temperature.is_some() || self.params.top_p.is_some() {
+                return Err(
+                    ProcessorError::ConfigError(
+                        format!("{} does not accept temperature or top_p, leave them unset", self.model)
+                    )
+                );
+            }
+        } else {
+            request_body["temperature"] = json!(self.params.temperature.unwrap_or(DEFAULT_TEMPERATURE));
+            if let Some(top_p) = self.params.top_p {
+                request_body["top_p"] = json!(top_p);
+            }
+        }
+        if let Some(seed) = self.params.seed {
+            request_body["seed"] = json!(seed);
+        }
+        if !self.params.stop.is_empty() {
+            request_body["stop"] = json!(self.params.stop);
+        }
+        if let Some(response_format) = response_format {
+            request_body["response_format"] = response_format;
+        }
+
+        Ok(request_body)
+    }
+
+    fn is_reasoning_model(&self) -> bool {
+        REASONING_MODELS.iter().any(|name| self.model.starts_with(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_schema_support_follows_model_family() {
+        let supports = |model: &str| OpenAIProvider::new(Some(model.to_string())).supports_json_schema();
+        assert!(supports("gpt-4o"));
+        assert!(supports("gpt-4o-mini"));
+        assert!(supports("gpt-4.1-nano"));
+        assert!(supports("o4-mini-2025-04-16"));
+        assert!(!supports("gpt-4o-2024-05-13"));
+        assert!(!supports("o1-mini"));
+        assert!(!supports("gpt-4-turbo"));
+        assert!(!supports("gpt-3.5-turbo"));
+    }
+
+    #[test]
+    fn test_reasoning_models_get_no_sampling_parameters() {
+        let messages = [Message::user("Describe the image", vec!["aW1n".to_string()])];
+        let mut provider = OpenAIProvider::new(Some("o3".to_string()));
+        let body = provider.request_body(&messages, None).unwrap();
+        assert!(body.get("temperature").is_none());
+        assert!(body.get("top_p").is_none());
+
+        provider.set_generation_params(GenerationParams { temperature: Some(0.2), ..Default::default() });
+        let error = provider.request_body(&messages, None).unwrap_err();
+        assert!(matches!(error, ProcessorError::ConfigError(_)));
+
+        let body = OpenAIProvider::new(None).request_body(&messages, None).unwrap();
+        assert_eq!(body["temperature"], json!(0.0));
+    }
+
+    #[test]
+    fn test_user_text_is_sent_unchanged() {
+        let messages = [Message::user("Write alt text for the image", vec!["aW1n".to_string()])];
+        let body = OpenAIProvider::new(None).request_body(&messages, None).unwrap();
+        assert_eq!(body["messages"][1]["content"][0]["text"], json!("Write alt text for the image"));
+    }
+
+    #[test]
+    fn test_grounding_support_follows_model_family() {
+        let supports = |model: &str| OpenAIProvider::new(Some(model.to_string())).supports_grounding();
+        assert!(supports("gpt-4.1"));
+        assert!(supports("gpt-5-mini"));
+        assert!(supports("o3"));
+        assert!(!supports("gpt-4o"));
+        assert!(!supports("gpt-4-turbo"));
+    }
 }
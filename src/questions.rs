@@ -0,0 +1,350 @@
+use crate::{ errors::ProcessorError, repair::repair_json };
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+
+/// Names accepted by [`AnswerType::from_name`].
+pub const ANSWER_TYPES: [&str; 5] = ["boolean", "number", "string", "enum", "json"];
+
+/// The form an answer must take.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnswerType {
+    Boolean,
+    Number,
+    #[default]
+    String,
+    /// One of a fixed list of values
+    Enum {
+        values: Vec<String>,
+    },
+    /// Any JSON matching a JSON Schema
+    Json {
+        schema: Value,
+    },
+}
+
+/// Answer to a question about an image.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Answer {
+    /// Typed as requested, or null when the image does not show the answer
+    pub answer: Value,
+    /// How sure the model is, from 0.0 to 1.0
+    pub confidence: f32,
+    /// What in the image the answer is based on
+    pub explanation: String,
+    /// `builtin`, or the `name@version` of the prompt template used
+    #[serde(skip_deserializing)]
+    pub prompt_version: String,
+}
+
+impl AnswerType {
+    /// Builds an answer type from its name, with the values for `enum` and the schema
+    /// for `json`.
+    pub fn from_name(
+        name: &str,
+        values: Vec<String>,
+        schema: Option<Value>
+    ) -> Result<Self, ProcessorError> {
+        let invalid = |message: String| ProcessorError::InvalidQuestion(message);
+        match name.trim().to_lowercase().as_str() {
+            "boolean" => Ok(Self::Boolean),
+            "number" => Ok(Self::Number),
+            "string" => Ok(Self::String),
+            "enum" if values.is_empty() => Err(invalid("enum answers need a list of values".to_string())),
+            "enum" => Ok(Self::Enum { values }),
+            "json" =>
+                match schema {
+                    Some(schema) if schema.is_object() => Ok(Self::Json { schema }),
+                    _ => Err(invalid("json answers need a JSON Schema object".to_string())),
+                }
+            other =>
+                Err(
+                    invalid(
+                        format!(
+                            "Unknown answer type {:?}, expected one of: {}",
+                            other,
+                            ANSWER_TYPES.join(", ")
+                        )
+                    )
+                ),
+        }
+    }
+
+    /// Whether [`schema`] can be enforced by providers with strict schema support;
+    /// caller-supplied schemas may not meet their restrictions.
+    pub fn is_strict(&self) -> bool {
+        !matches!(self, Self::Json { .. })
+    }
+
+    /// The expected form of the answer, for prompts.
+    pub fn description(&self) -> String {
+        match self {
+            Self::Boolean => "true or false".to_string(),
+            Self::Number => "a number, without units or thousands separators".to_string(),
+            Self::String => "a short string".to_string(),
+            Self::Enum { values } =>
+                format!(
+                    "exactly one of: {}",
+                    values
+                        .iter()
+                        .map(|v| format!("{:?}", v))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            Self::Json { schema } =>
+                format!(
+                    "JSON matching this schema:\n{}",
+                    serde_json::to_string_pretty(schema).unwrap_or_default()
+                ),
+        }
+    }
+
+    fn answer_schema(&self) -> Value {
+        match self {
+            Self::Boolean => json!({ "type": ["boolean", "null"] }),
+            Self::Number => json!({ "type": ["number", "null"] }),
+            Self::String => json!({ "type": ["string", "null"] }),
+            Self::Enum { values } => {
+                let mut allowed: Vec<Value> = values.iter().map(|v| json!(v)).collect();
+                allowed.push(Value::Null);
+                json!({ "type": ["string", "null"], "enum": allowed })
+            }
+            Self::Json { schema } => json!({ "anyOf": [schema, { "type": "null" }] }),
+        }
+    }
+}
+
+/// Instructions for answering `question` in the form of `answer_type`.
+pub fn prompt(question: &str, answer_type: &AnswerType) -> String {
+    format!(
+        r#"Answer this question about the image: {}
+
+- answer must be {}.
+- Use null as the answer when the image does not show enough to answer.
+- confidence is how sure you are, from 0.0 to 1.0.
+- explanation briefly names what in the image the answer is based on.
+
+Respond with JSON only:
+{{
+    "answer": ...,
+    "confidence": 0.0-1.0,
+    "explanation": "string"
+}}"#,
+        question.trim(),
+        answer_type.description()
+    )
+}
+
+/// JSON Schema of the response for `answer_type`.
+pub fn schema(answer_type: &AnswerType) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "answer": answer_type.answer_schema(),
+            "confidence": { "type": "number", "minimum": 0.0, "maximum": 1.0 },
+            "explanation": { "type": "string" }
+        },
+        "required": ["answer", "confidence", "explanation"],
+        "additionalProperties": false
+    })
+}
+
+impl Answer {
+    /// Parses provider output and checks the answer against `answer_type`, converting
+    /// answers given as strings such as `"yes"` or `"12.50"` to the requested type.
+    pub fn parse(output: &str, answer_type: &AnswerType) -> Result<Self, ProcessorError> {
+        let (json, _) = repair_json(output);
+        let mut answer: Self = serde_json::from_str(&json).map_err(|e| {
+            ProcessorError::ResponseParseError(
+                format!("Failed to parse answer: {}. Response text: {}", e, output)
+            )
+        })?;
+        answer.confidence = answer.confidence.clamp(0.0, 1.0);
+        if !answer.answer.is_null() {
+            answer.answer = coerce(answer.answer, answer_type)?;
+        }
+        Ok(answer)
+    }
+}
+
+fn coerce(value: Value, answer_type: &AnswerType) -> Result<Value, ProcessorError> {
+    let violation = |message: String| ProcessorError::SchemaViolation(vec![message]);
+    match (answer_type, value) {
+        (AnswerType::Boolean, Value::Bool(b)) => Ok(Value::Bool(b)),
+        (AnswerType::Boolean, Value::String(s)) =>
+            match s.trim().to_lowercase().as_str() {
+                "true" | "yes" => Ok(Value::Bool(true)),
+                "false" | "no" => Ok(Value::Bool(false)),
+                _ => Err(violation(format!("answer: expected a boolean, got {:?}", s))),
+            }
+        (AnswerType::Number, Value::Number(n)) => Ok(Value::Number(n)),
+        (AnswerType::Number, Value::String(s)) =>
+            parse_number(&s)
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number)
+                .ok_or_else(|| violation(format!("answer: expected a number, got {:?}", s))),
+        (AnswerType::String, Value::String(s)) => Ok(Value::String(s)),
+        (AnswerType::String, other) => Ok(Value::String(other.to_string())),
+        (AnswerType::Enum { values }, Value::String(s)) =>
+            values
+                .iter()
+                .find(|v| v.eq_ignore_ascii_case(s.trim()))
+                .map(|v| Value::String(v.clone()))
+                .ok_or_else(|| {
+                    violation(format!("answer: {:?} is not one of {}", s, values.join(", ")))
+                }),
+        (AnswerType::Json { schema }, value) => {
+            let mut violations = Vec::new();
+            check(&value, schema, "answer", &mut violations);
+            if violations.is_empty() {
+                Ok(value)
+            } else {
+                Err(ProcessorError::SchemaViolation(violations))
+            }
+        }
+        (answer_type, value) =>
+            Err(violation(format!("answer: expected {}, got {}", answer_type.description(), value))),
+    }
+}
+
+// Accepts commas only as thousands separators, as in 1,204.50; a decimal comma such as
+// 12,50 is ambiguous and rejected
+fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if !text.contains(',') {
+        return text.parse().ok();
+    }
+    let unsigned = text.strip_prefix(['-', '+']).unwrap_or(text);
+    let integer = unsigned.split('.').next().unwrap_or("");
+    let mut groups = integer.split(',');
+    let leading = groups.next().unwrap_or("");
+    let grouped = (1..=3).contains(&leading.len()) &&
+        leading.bytes().all(|b| b.is_ascii_digit()) &&
+        groups.all(|group| group.len() == 3 && group.bytes().all(|b| b.is_ascii_digit()));
+    if !grouped {
+        return None;
+    }
+    text.replace(',', "").parse().ok()
+}
+
+/// Checks the common JSON Schema keywords: `type`, `enum`, `properties`, `required` and `items`.
+fn check(value: &Value, schema: &Value, path: &str, violations: &mut Vec<String>) {
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let matches = |t: &str| {
+            match t {
+                "object" => value.is_object(),
+                "array" => value.is_array(),
+                "string" => value.is_string(),
+                "number" => value.is_number(),
+                "integer" => value.is_i64() || value.is_u64(),
+                "boolean" => value.is_boolean(),
+                "null" => value.is_null(),
+                _ => true,
+            }
+        };
+        if !types.is_empty() && !types.iter().any(|t| matches(t)) {
+            violations.push(format!("{}: expected {}, got {}", path, types.join(" or "), value));
+            return;
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            violations.push(format!("{}: {} is not an allowed value", path, value));
+        }
+    }
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    violations.push(format!("{}.{}: missing", path, name));
+                }
+            }
+        }
+        if let Some(Value::Object(properties)) = schema.get("properties") {
+            for (name, property) in properties {
+                if let Some(field) = object.get(name) {
+                    check(field, property, &format!("{}.{}", path, name), violations);
+                }
+            }
+        }
+    }
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check(item, item_schema, &format!("{}[{}]", path, i), violations);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answers_are_coerced_to_the_type() {
+        let output = r#"{"answer": "Yes", "confidence": 1.4, "explanation": "A dog sits on the sofa"}"#;
+        let answer = Answer::parse(output, &AnswerType::Boolean).unwrap();
+        assert_eq!(answer.answer, json!(true));
+        assert_eq!(answer.confidence, 1.0);
+
+        let output = r#"{"answer": "1,204.50", "confidence": 0.8, "explanation": ""}"#;
+        assert_eq!(Answer::parse(output, &AnswerType::Number).unwrap().answer, json!(1204.5));
+        let output = r#"{"answer": "-12,345,678", "confidence": 0.8, "explanation": ""}"#;
+        assert_eq!(Answer::parse(output, &AnswerType::Number).unwrap().answer, json!(-12345678.0));
+        for ambiguous in ["12,50", "1,2345", "1,234,5", ",500"] {
+            let output = format!(r#"{{"answer": "{}", "confidence": 0.8, "explanation": ""}}"#, ambiguous);
+            assert!(matches!(Answer::parse(&output, &AnswerType::Number), Err(ProcessorError::SchemaViolation(_))));
+        }
+
+        let colors = AnswerType::Enum { values: vec!["Red".to_string(), "Blue".to_string()] };
+        let output = r#"{"answer": "blue", "confidence": 0.9, "explanation": ""}"#;
+        assert_eq!(Answer::parse(output, &colors).unwrap().answer, json!("Blue"));
+        let output = r#"{"answer": "green", "confidence": 0.9, "explanation": ""}"#;
+        assert!(matches!(Answer::parse(output, &colors), Err(ProcessorError::SchemaViolation(_))));
+
+        let output = r#"{"answer": null, "confidence": 0.2, "explanation": "The label is cut off"}"#;
+        assert_eq!(Answer::parse(output, &AnswerType::Number).unwrap().answer, Value::Null);
+    }
+
+    #[test]
+    fn test_json_answers_are_checked_against_the_schema() {
+        let answer_type = AnswerType::Json {
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "people": { "type": "integer" },
+                    "names": { "type": "array", "items": { "type": "string" } }
+                },
+                "required": ["people", "names"]
+            }),
+        };
+        let output = r#"{"answer": {"people": 2, "names": ["Ann", "Bo"]}, "confidence": 0.7}"#;
+        assert!(Answer::parse(output, &answer_type).is_ok());
+
+        let output = r#"{"answer": {"people": "two", "names": ["Ann", 3]}, "confidence": 0.7}"#;
+        match Answer::parse(output, &answer_type) {
+            Err(ProcessorError::SchemaViolation(violations)) => {
+                assert_eq!(violations, vec![
+                    "answer.people: expected integer, got \"two\"",
+                    "answer.names[1]: expected string, got 3"
+                ]);
+            }
+            other => panic!("expected schema violations, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_answer_type_from_name() {
+        assert_eq!(AnswerType::from_name("Boolean", Vec::new(), None).unwrap(), AnswerType::Boolean);
+        assert!(AnswerType::from_name("enum", Vec::new(), None).is_err());
+        assert!(AnswerType::from_name("json", Vec::new(), Some(json!(true))).is_err());
+        assert!(AnswerType::from_name("date", Vec::new(), None).is_err());
+        let schema = schema(&AnswerType::Enum { values: vec!["a".to_string()] });
+        assert_eq!(schema["properties"]["answer"]["enum"], json!(["a", null]));
+    }
+}
@@ -0,0 +1,335 @@
+use serde::Serialize;
+
+/// A fix applied to model output so that it parses as JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Repair {
+    /// Removed a markdown code fence around the JSON.
+    StrippedCodeFence,
+    /// Removed prose before or after the outermost object.
+    StrippedSurroundingText,
+    /// Removed commas directly before a closing `}` or `]`.
+    RemovedTrailingCommas,
+    /// Removed `//` or `/* */` comments.
+    RemovedComments,
+    /// Escaped raw newlines, tabs or other control characters inside strings.
+    EscapedControlCharacters,
+    /// Closed strings, objects and arrays left open by truncated output.
+    ClosedTruncatedOutput,
+}
+
+/// What the repair stage did to a provider response.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RepairReport {
+    pub repairs: Vec<Repair>,
+    /// The output ended before the outermost object was closed.
+    pub truncated: bool,
+    /// Number of follow-up requests made to continue truncated output.
+    pub continuations: usize,
+}
+
+impl RepairReport {
+    pub fn is_clean(&self) -> bool {
+        self.repairs.is_empty() && self.continuations == 0
+    }
+
+    fn record(&mut self, repair: Repair) {
+        if !self.repairs.contains(&repair) {
+            self.repairs.push(repair);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frame {
+    Array,
+    Object(Expect),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Expect {
+    Key,
+    Colon,
+    Value,
+    Next,
+}
+
+/// Extracts the outermost JSON object from model output and fixes common mistakes.
+///
+/// Output without any `{` is returned unchanged.
+pub fn repair_json(raw: &str) -> (String, RepairReport) {
+    let mut report = RepairReport::default();
+
+    let unfenced = strip_code_fence(raw);
+    if unfenced.len() != raw.len() {
+        report.record(Repair::StrippedCodeFence);
+    }
+
+    let Some(start) = unfenced.find('{') else {
+        return (raw.to_string(), report);
+    };
+    if !unfenced[..start].trim().is_empty() {
+        report.record(Repair::StrippedSurroundingText);
+    }
+
+    let source = &unfenced[start..];
+    let mut out = String::with_capacity(source.len());
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut end = None;
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if in_string {
+            if escaped {
+                escaped = false;
+                out.push(c);
+            } else if c == '\\' {
+                escaped = true;
+                out.push(c);
+            } else if c == '"' {
+                in_string = false;
+                out.push(c);
+                string_closed(&mut stack);
+            } else if c.is_control() {
+                report.record(Repair::EscapedControlCharacters);
+                match c {
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    other => out.push_str(&format!("\\u{:04x}", other as u32)),
+                }
+            } else {
+                out.push(c);
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '/' if matches!(chars.peek(), Some((_, '/'))) => {
+                report.record(Repair::RemovedComments);
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                report.record(Repair::RemovedComments);
+                chars.next();
+                let mut previous = ' ';
+                for (_, c) in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '{' | '[' => {
+                value_started(&mut stack);
+                stack.push(if c == '{' { Frame::Object(Expect::Key) } else { Frame::Array });
+                out.push(c);
+            }
+            '}' | ']' => {
+                if drop_trailing_comma(&mut out) {
+                    report.record(Repair::RemovedTrailingCommas);
+                }
+                stack.pop();
+                out.push(c);
+                if stack.is_empty() {
+                    end = Some(i + c.len_utf8());
+                    break;
+                }
+            }
+            ':' => {
+                if let Some(Frame::Object(expect)) = stack.last_mut() {
+                    *expect = Expect::Value;
+                }
+                out.push(c);
+            }
+            ',' => {
+                if let Some(Frame::Object(expect)) = stack.last_mut() {
+                    *expect = Expect::Key;
+                }
+                out.push(c);
+            }
+            c if c.is_whitespace() => out.push(c),
+            c => {
+                value_started(&mut stack);
+                out.push(c);
+            }
+        }
+    }
+
+    match end {
+        Some(end) => {
+            if !source[end..].trim().is_empty() {
+                report.record(Repair::StrippedSurroundingText);
+            }
+        }
+        None => {
+            report.truncated = true;
+            report.record(Repair::ClosedTruncatedOutput);
+            close_truncated(&mut out, &mut stack, in_string, escaped);
+        }
+    }
+
+    (out, report)
+}
+
+/// Returns the body of the first markdown fence, including an unclosed one from truncated
+/// output. Text without a fence is returned unchanged.
+pub fn strip_code_fence(text: &str) -> &str {
+    let Some(open) = text.find("```") else {
+        return text;
+    };
+    // A fence after the first brace belongs to a string value, not the wrapper
+    if text.find('{').is_some_and(|brace| brace < open) {
+        return text;
+    }
+    let rest = &text[open + 3..];
+    // Skip the language tag on the opening line
+    let body = rest.split_once('\n').map(|(_, body)| body).unwrap_or("");
+    match body.rfind("```") {
+        Some(close) => &body[..close],
+        None => body,
+    }
+}
+
+fn value_started(stack: &mut [Frame]) {
+    if let Some(Frame::Object(expect)) = stack.last_mut() {
+        if *expect == Expect::Value {
+            *expect = Expect::Next;
+        }
+    }
+}
+
+fn string_closed(stack: &mut [Frame]) {
+    if let Some(Frame::Object(expect)) = stack.last_mut() {
+        *expect = match expect {
+            Expect::Key => Expect::Colon,
+            _ => Expect::Next,
+        };
+    }
+}
+
+fn drop_trailing_comma(out: &mut String) -> bool {
+    let trimmed = out.trim_end();
+    if trimmed.ends_with(',') {
+        out.truncate(trimmed.len() - 1);
+        true
+    } else {
+        false
+    }
+}
+
+fn close_truncated(out: &mut String, stack: &mut Vec<Frame>, in_string: bool, escaped: bool) {
+    if in_string {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+        string_closed(stack);
+    } else {
+        // Drop a bare literal cut off mid-token, such as `tru` or `0.`
+        let token_start = out
+            .char_indices()
+            .rev()
+            .find(|(_, c)| "{}[],:\"".contains(*c) || c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let token = &out[token_start..];
+        if !token.is_empty() && serde_json::from_str::<serde_json::Value>(token).is_err() {
+            out.truncate(token_start);
+            if let Some(Frame::Object(expect)) = stack.last_mut() {
+                *expect = Expect::Value;
+            }
+        }
+    }
+
+    let trimmed_len = out.trim_end().len();
+    out.truncate(trimmed_len);
+
+    match stack.last() {
+        Some(Frame::Object(Expect::Colon)) => out.push_str(": null"),
+        Some(Frame::Object(Expect::Value)) if out.ends_with(':') => out.push_str(" null"),
+        _ => {
+            drop_trailing_comma(out);
+        }
+    }
+
+    while let Some(frame) = stack.pop() {
+        out.push(match frame {
+            Frame::Array => ']',
+            Frame::Object(_) => '}',
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parses(text: &str) -> serde_json::Value {
+        serde_json::from_str(text).unwrap_or_else(|e| panic!("{}: {}", e, text))
+    }
+
+    #[test]
+    fn test_clean_output_untouched() {
+        let (text, report) = repair_json(r#"{"a": [1, 2], "b": "x"}"#);
+        assert_eq!(text, r#"{"a": [1, 2], "b": "x"}"#);
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn test_fence_preamble_and_trailing_commas() {
+        let raw = "Here is the analysis:\n```json\n{\"a\": [1, 2,], \"b\": {\"c\": 1,},}\n```\nHope it helps";
+        let (text, report) = repair_json(raw);
+        assert_eq!(parses(&text)["b"]["c"], 1);
+        assert!(report.repairs.contains(&Repair::StrippedCodeFence));
+        assert!(report.repairs.contains(&Repair::RemovedTrailingCommas));
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn test_comments_and_control_characters() {
+        let raw = "{\"a\": \"line one\nline two\", // note\n\"b\": /* inline */ 2}";
+        let (text, report) = repair_json(raw);
+        let value = parses(&text);
+        assert_eq!(value["a"], "line one\nline two");
+        assert_eq!(value["b"], 2);
+        assert!(report.repairs.contains(&Repair::RemovedComments));
+        assert!(report.repairs.contains(&Repair::EscapedControlCharacters));
+    }
+
+    #[test]
+    fn test_truncated_output() {
+        for raw in [
+            r#"{"a": {"b": ["x", "y"#,
+            r#"{"a": {"b": ["x", "#,
+            r#"{"a": 1, "b"#,
+            r#"{"a": 1, "b":"#,
+            r#"{"a": tru"#,
+            "```json\n{\"a\": [{\"b\": 1},",
+        ] {
+            let (text, report) = repair_json(raw);
+            parses(&text);
+            assert!(report.truncated, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_fence_inside_string_kept() {
+        let (text, report) = repair_json(r#"{"code": "```rust```"}"#);
+        assert_eq!(parses(&text)["code"], "```rust```");
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn test_braces_inside_strings() {
+        let (text, report) = repair_json(r#"{"a": "}{][,"} trailing"#);
+        assert_eq!(parses(&text)["a"], "}{][,");
+        assert_eq!(report.repairs, vec![Repair::StrippedSurroundingText]);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::providers::Message;
+use parking_lot::Mutex;
+use std::{ collections::HashMap, time::{ Duration, Instant } };
+
+/// How long a session is kept after its last use.
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Sessions kept at once; the least recently used one is dropped beyond this.
+pub const DEFAULT_MAX_SESSIONS: usize = 1000;
+
+/// Total size of the sessions kept at once, in bytes; the least recently used ones are
+/// dropped beyond this.
+pub const DEFAULT_MAX_SESSION_BYTES: usize = 512 * 1024 * 1024;
+
+/// Follow-up questions allowed per conversation, as every question resends the history.
+pub const MAX_QUESTIONS: usize = 20;
+
+/// Sent with every follow-up question.
+pub const FOLLOW_UP_INSTRUCTIONS: &str =
+    "Answer this follow-up question about the image using what you can see in it and your earlier analysis. Answer in plain text, briefly, and say so if the image does not show the answer.";
+
+/// An analysis and the follow-up questions about it, resent as prior turns with
+/// every new question.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversation {
+    pub messages: Vec<Message>,
+}
+
+impl Conversation {
+    /// Starts a conversation from the analyzed images, the prompt and the model's analysis.
+    pub fn new(base64_images: Vec<String>, prompt: &str, analysis: &str) -> Self {
+        Self {
+            messages: vec![Message::user(prompt, base64_images), Message::assistant(analysis)],
+        }
+    }
+
+    /// Number of follow-up questions answered so far.
+    pub fn questions(&self) -> usize {
+        self.messages.len().saturating_sub(2) / 2
+    }
+
+    /// Approximate memory held by the conversation, mostly its base64 images.
+    pub fn size(&self) -> usize {
+        self.messages
+            .iter()
+            .map(|m| m.text.len() + m.images.iter().map(String::len).sum::<usize>())
+            .sum()
+    }
+}
+
+struct Entry<T> {
+    value: T,
+    last_used: Instant,
+    bytes: usize,
+}
+
+/// Values kept in memory by id, dropped when unused for longer than the TTL.
+pub struct SessionStore<T> {
+    sessions: Mutex<HashMap<String, Entry<T>>>,
+    ttl: Duration,
+    max_sessions: usize,
+    max_bytes: usize,
+}
+
+impl<T: Clone> SessionStore<T> {
+    pub fn new(ttl: Duration, max_sessions: usize) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            ttl,
+            max_sessions,
+            max_bytes: DEFAULT_MAX_SESSION_BYTES,
+        }
+    }
+
+    /// Limits the total size given to [`SessionStore::insert_sized`].
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Stores a value under a new random id.
+    pub fn insert(&self, value: T) -> String {
+        self.insert_sized(value, 0).expect("values without a size always fit")
+    }
+
+    /// Stores a value taking `bytes` of memory under a new random id, dropping the least
+    /// recently used values to stay within the limits. Returns `None`, storing nothing,
+    /// when the value alone exceeds the byte limit.
+    pub fn insert_sized(&self, value: T, bytes: usize) -> Option<String> {
+        if bytes > self.max_bytes {
+            return None;
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        let mut sessions = self.sessions.lock();
+        Self::evict(&mut sessions, self.ttl);
+        let mut total: usize = sessions.values().map(|entry| entry.bytes).sum();
+        while sessions.len() >= self.max_sessions.max(1) || total + bytes > self.max_bytes {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| id.clone());
+            match oldest.and_then(|oldest| sessions.remove(&oldest)) {
+                Some(entry) => {
+                    total -= entry.bytes;
+                }
+                None => {
+                    break;
+                }
+            }
+        }
+        sessions.insert(id.clone(), Entry { value, last_used: Instant::now(), bytes });
+        Some(id)
+    }
+
+    /// Returns a copy of the value and extends its lifetime, or `None` when it is
+    /// unknown or expired.
+    pub fn get(&self, id: &str) -> Option<T> {
+        let mut sessions = self.sessions.lock();
+        let entry = sessions.get_mut(id)?;
+        if entry.last_used.elapsed() >= self.ttl {
+            sessions.remove(id);
+            return None;
+        }
+        entry.last_used = Instant::now();
+        Some(entry.value.clone())
+    }
+
+    /// Replaces the value of a session that still exists.
+    pub fn update(&self, id: &str, value: T) -> bool {
+        match self.sessions.lock().get_mut(id) {
+            Some(entry) => {
+                entry.value = value;
+                entry.last_used = Instant::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        self.sessions.lock().remove(id).is_some()
+    }
+
+    /// Drops expired sessions, returning how many were dropped.
+    pub fn evict_expired(&self) -> usize {
+        Self::evict(&mut self.sessions.lock(), self.ttl)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn evict(sessions: &mut HashMap<String, Entry<T>>, ttl: Duration) -> usize {
+        let before = sessions.len();
+        sessions.retain(|_, entry| entry.last_used.elapsed() < ttl);
+        before - sessions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_expire() {
+        let store = SessionStore::new(Duration::ZERO, 10);
+        let id = store.insert("receipt");
+        assert_eq!(store.get(&id), None);
+        store.insert("chart");
+        assert_eq!(store.evict_expired(), 1);
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_least_recently_used_session_is_dropped() {
+        let store = SessionStore::new(DEFAULT_SESSION_TTL, 2);
+        let first = store.insert(1);
+        let second = store.insert(2);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(store.get(&first), Some(1));
+        let third = store.insert(3);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(&second), None);
+        assert!(store.update(&third, 4));
+        assert_eq!(store.get(&third), Some(4));
+        assert!(store.remove(&first));
+        assert!(!store.update(&first, 5));
+    }
+
+    #[test]
+    fn test_sessions_stay_within_byte_limit() {
+        let store = SessionStore::new(DEFAULT_SESSION_TTL, 10).with_max_bytes(100);
+        let first = store.insert_sized("receipt", 60).unwrap();
+        let second = store.insert_sized("chart", 40).unwrap();
+        assert!(store.insert_sized("poster", 101).is_none());
+        store.insert_sized("diagram", 30).unwrap();
+        assert_eq!(store.get(&first), None);
+        assert_eq!(store.get(&second), Some("chart"));
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn test_conversation_counts_questions() {
+        let mut conversation = Conversation::new(vec!["aW1n".to_string()], "Analyze", "{}");
+        assert_eq!(conversation.questions(), 0);
+        conversation.messages.push(Message::user("What is the total?", Vec::new()));
+        conversation.messages.push(Message::assistant("$12.40"));
+        assert_eq!(conversation.questions(), 1);
+    }
+}
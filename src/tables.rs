@@ -0,0 +1,466 @@
+use crate::{ errors::ProcessorError, prompts::NUMBER, utils::csv_field };
+use rust_xlsxwriter::{ Format, Workbook };
+use serde::{ Deserialize, Deserializer, Serialize };
+use serde_json::{ json, Value };
+
+/// A table as read by the model: rows of cells, where a merged cell appears once,
+/// in the first row and column it covers, like an HTML table.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Table {
+    pub title: String,
+    /// Number of leading rows that are column headers
+    #[serde(deserialize_with = "count")]
+    pub header_rows: usize,
+    pub rows: Vec<Vec<TableCell>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TableCell {
+    pub text: String,
+    #[serde(deserialize_with = "span")]
+    pub row_span: usize,
+    #[serde(deserialize_with = "span")]
+    pub col_span: usize,
+}
+
+impl Default for TableCell {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            row_span: 1,
+            col_span: 1,
+        }
+    }
+}
+
+/// A table laid out on a rectangular grid.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TableGrid {
+    pub title: String,
+    pub header_rows: usize,
+    /// Cell text by row and column; cells covered by a merge are empty
+    pub cells: Vec<Vec<String>>,
+    pub merges: Vec<Merge>,
+}
+
+/// A merged range, anchored at its top-left cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Merge {
+    pub row: usize,
+    pub column: usize,
+    pub row_span: usize,
+    pub col_span: usize,
+}
+
+/// Structure requested under `extracted_data.tables`.
+pub fn structure() -> Value {
+    json!([{
+        "title": "string",
+        "header_rows": NUMBER,
+        "rows": [[{ "text": "string", "row_span": NUMBER, "col_span": NUMBER }]]
+    }])
+}
+
+/// Largest row or column span accepted from the model.
+const MAX_SPAN: usize = 100;
+
+/// Worksheet size limits of the XLSX format.
+const XLSX_MAX_ROWS: usize = 1_048_576;
+const XLSX_MAX_COLUMNS: usize = 16_384;
+
+/// Reading rules added to the prompt when table extraction is enabled.
+pub const INSTRUCTIONS: &str =
+    "Reproduce every table cell by cell, including empty cells as empty strings. List a merged cell only once, in the first row and column it covers, with its row_span and col_span; use 1 for unmerged cells. Set header_rows to the number of leading header rows.";
+
+// Models often write counts as floats or leave them out
+fn count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let value = Option::<f64>::deserialize(deserializer)?;
+    Ok(value.filter(|v| v.is_finite() && *v > 0.0).map_or(0, |v| v.round() as usize))
+}
+
+fn span<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    count(deserializer).map(|span| span.clamp(1, MAX_SPAN))
+}
+
+impl Table {
+    /// Places every cell on a grid, expanding merged cells over the positions they cover.
+    ///
+    /// Row spans end at the last row and column spans before positions already covered
+    /// by a cell above, so merges never overlap.
+    pub fn grid(&self) -> TableGrid {
+        let mut occupied: Vec<Vec<bool>> = Vec::new();
+        let mut cells: Vec<Vec<String>> = Vec::new();
+        let mut merges = Vec::new();
+
+        fn ensure(grid: &mut Vec<Vec<bool>>, text: &mut Vec<Vec<String>>, rows: usize, columns: usize) {
+            while grid.len() < rows {
+                grid.push(Vec::new());
+                text.push(Vec::new());
+            }
+            for (occupied, text) in grid.iter_mut().zip(text.iter_mut()) {
+                if occupied.len() < columns {
+                    occupied.resize(columns, false);
+                    text.resize(columns, String::new());
+                }
+            }
+        }
+
+        for (r, row) in self.rows.iter().enumerate() {
+            ensure(&mut occupied, &mut cells, r + 1, 0);
+            let mut c = 0;
+            for cell in row {
+                while occupied[r].get(c).copied().unwrap_or(false) {
+                    c += 1;
+                }
+                let row_span = cell.row_span.min(self.rows.len() - r);
+                let free = occupied[r]
+                    .iter()
+                    .skip(c)
+                    .position(|taken| *taken)
+                    .unwrap_or(usize::MAX);
+                let col_span = cell.col_span.min(free);
+                ensure(&mut occupied, &mut cells, r + row_span, c + col_span);
+                for covered in occupied.iter_mut().skip(r).take(row_span) {
+                    for slot in covered.iter_mut().skip(c).take(col_span) {
+                        *slot = true;
+                    }
+                }
+                cells[r][c] = cell.text.clone();
+                if row_span > 1 || col_span > 1 {
+                    merges.push(Merge { row: r, column: c, row_span, col_span });
+                }
+                c += col_span;
+            }
+        }
+
+        // Pad ragged rows so every row has the same number of columns
+        let columns = cells
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0);
+        let rows = cells.len();
+        ensure(&mut occupied, &mut cells, rows, columns);
+
+        TableGrid {
+            title: self.title.clone(),
+            header_rows: self.header_rows.min(rows),
+            cells,
+            merges,
+        }
+    }
+}
+
+impl TableGrid {
+    pub fn columns(&self) -> usize {
+        self.cells.first().map_or(0, Vec::len)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        for row in &self.cells {
+            let fields: Vec<String> = row
+                .iter()
+                .map(|cell| csv_field(cell))
+                .collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Renders a GitHub-flavored Markdown table.
+    ///
+    /// Markdown has a single header row, so multiple header rows are joined per column.
+    /// Without header rows, columns are numbered.
+    pub fn to_markdown(&self) -> String {
+        let columns = self.columns();
+        if columns == 0 {
+            return String::new();
+        }
+
+        let header: Vec<String> = (0..columns)
+            .map(|c| {
+                let mut parts: Vec<&str> = Vec::new();
+                for row in &self.cells[..self.header_rows] {
+                    let text = self.merged_text(row, c);
+                    if !text.is_empty() && !parts.contains(&text) {
+                        parts.push(text);
+                    }
+                }
+                if self.header_rows == 0 {
+                    format!("Column {}", c + 1)
+                } else {
+                    parts.join(" ")
+                }
+            })
+            .collect();
+
+        let mut markdown = String::new();
+        let line = |cells: &[String]| {
+            let escaped: Vec<String> = cells
+                .iter()
+                .map(|c| c.replace('|', "\\|").replace('\n', "<br>"))
+                .collect();
+            format!("| {} |\n", escaped.join(" | "))
+        };
+        markdown.push_str(&line(&header));
+        markdown.push_str(&format!("|{}\n", " --- |".repeat(columns)));
+        for row in &self.cells[self.header_rows..] {
+            markdown.push_str(&line(row));
+        }
+        markdown
+    }
+
+    // Header text for a column, looking left through horizontally merged cells
+    fn merged_text<'a>(&self, row: &'a [String], column: usize) -> &'a str {
+        (0..=column)
+            .rev()
+            .find(|c| !row[*c].is_empty())
+            .filter(|c| {
+                *c == column ||
+                    self.merges
+                        .iter()
+                        .any(|m| m.column == *c && m.column + m.col_span > column)
+            })
+            .map_or("", |c| row[c].as_str())
+    }
+}
+
+/// Writes the tables to an XLSX workbook, one worksheet per table, keeping merged
+/// cells and marking header rows in bold.
+pub fn to_xlsx(tables: &[TableGrid]) -> Result<Vec<u8>, ProcessorError> {
+    let xlsx_error = |e: rust_xlsxwriter::XlsxError| {
+        ProcessorError::ExportError(format!("Failed to write XLSX: {}", e))
+    };
+
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+    let plain = Format::new();
+
+    for (i, table) in tables.iter().enumerate() {
+        if table.cells.len() > XLSX_MAX_ROWS || table.columns() > XLSX_MAX_COLUMNS {
+            return Err(
+                ProcessorError::ExportError(
+                    format!(
+                        "Table {} has {} rows and {} columns, XLSX allows at most {} and {}",
+                        i + 1,
+                        table.cells.len(),
+                        table.columns(),
+                        XLSX_MAX_ROWS,
+                        XLSX_MAX_COLUMNS
+                    )
+                )
+            );
+        }
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(sheet_name(&table.title, i)).map_err(xlsx_error)?;
+
+        for (r, row) in table.cells.iter().enumerate() {
+            let format = if r < table.header_rows { &bold } else { &plain };
+            for (c, text) in row.iter().enumerate() {
+                if text.is_empty() {
+                    continue;
+                }
+                match xlsx_number(text) {
+                    Some(number) => {
+                        sheet.write_number_with_format(r as u32, c as u16, number, format)
+                    }
+                    None => sheet.write_string_with_format(r as u32, c as u16, text, format),
+                }.map_err(xlsx_error)?;
+            }
+        }
+
+        for merge in &table.merges {
+            let format = if merge.row < table.header_rows { &bold } else { &plain };
+            sheet
+                .merge_range(
+                    merge.row as u32,
+                    merge.column as u16,
+                    (merge.row + merge.row_span - 1) as u32,
+                    (merge.column + merge.col_span - 1) as u16,
+                    &table.cells[merge.row][merge.column],
+                    format
+                )
+                .map_err(xlsx_error)?;
+        }
+    }
+
+    if tables.is_empty() {
+        workbook.add_worksheet();
+    }
+    workbook.save_to_buffer().map_err(xlsx_error)
+}
+
+/// Excel keeps 15 significant digits of a number.
+const XLSX_MAX_DIGITS: usize = 15;
+
+// Only plain decimals that read back unchanged become numbers, so codes with leading
+// zeros, exponents and long IDs stay text. Trailing zeros after the point are allowed.
+fn xlsx_number(text: &str) -> Option<f64> {
+    let number = text.parse::<f64>().ok().filter(|number| number.is_finite())?;
+    let normalized = if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        text
+    };
+    let digits = normalized
+        .trim_start_matches('-')
+        .trim_start_matches(['0', '.'])
+        .chars()
+        .filter(char::is_ascii_digit)
+        .count();
+    (number.to_string() == normalized && digits <= XLSX_MAX_DIGITS).then_some(number)
+}
+
+// Worksheet names are limited to 31 characters, exclude []:*?/\ and can't start or
+// end with an apostrophe
+fn sheet_name(title: &str, index: usize) -> String {
+    let cleaned: String = title
+        .chars()
+        .filter(|c| !"[]:*?/\\".contains(*c))
+        .take(25)
+        .collect();
+    let cleaned = cleaned.trim_matches(|c: char| c == '\'' || c.is_whitespace());
+    if cleaned.is_empty() {
+        format!("Table {}", index + 1)
+    } else {
+        format!("{} {}", cleaned, index + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(text: &str, row_span: usize, col_span: usize) -> TableCell {
+        TableCell { text: text.to_string(), row_span, col_span }
+    }
+
+    fn sales_table() -> Table {
+        Table {
+            title: "Sales".to_string(),
+            header_rows: 2,
+            rows: vec![
+                vec![cell("Region", 2, 1), cell("2024", 1, 2)],
+                vec![cell("H1", 1, 1), cell("H2", 1, 1)],
+                vec![cell("North", 1, 1), cell("10", 1, 1), cell("12", 1, 1)],
+                vec![cell("South, East", 1, 1), cell("8", 1, 1)]
+            ],
+        }
+    }
+
+    #[test]
+    fn test_grid_expands_merged_cells() {
+        let grid = sales_table().grid();
+        assert_eq!(grid.columns(), 3);
+        assert_eq!(grid.cells[1], vec!["", "H1", "H2"]);
+        assert_eq!(grid.cells[3], vec!["South, East", "8", ""]);
+        assert_eq!(grid.merges.len(), 2);
+    }
+
+    #[test]
+    fn test_csv_and_markdown() {
+        let grid = sales_table().grid();
+        assert_eq!(grid.to_csv(), "Region,2024,\n,H1,H2\nNorth,10,12\n\"South, East\",8,\n");
+        assert_eq!(
+            grid.to_markdown(),
+            "| Region | 2024 H1 | 2024 H2 |\n| --- | --- | --- |\n| North | 10 | 12 |\n| South, East | 8 |  |\n"
+        );
+    }
+
+    #[test]
+    fn test_xlsx_export() {
+        use std::io::Read;
+
+        let bytes = to_xlsx(&[sales_table().grid()]).unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut read = |name: &str| {
+            let mut xml = String::new();
+            archive.by_name(name).unwrap().read_to_string(&mut xml).unwrap();
+            xml
+        };
+
+        assert!(read("xl/workbook.xml").contains("<sheet name=\"Sales 1\""));
+        let strings = read("xl/sharedStrings.xml");
+        assert!(strings.contains("<t>Region</t>") && strings.contains("<t>South, East</t>"));
+        let sheet = read("xl/worksheets/sheet1.xml");
+        assert!(sheet.contains("<c r=\"B3\"><v>10</v></c>"));
+        assert!(sheet.contains("<mergeCell ref=\"A1:A2\"/>"));
+        assert!(sheet.contains("<mergeCell ref=\"B1:C1\"/>"));
+    }
+
+    #[test]
+    fn test_sheet_names() {
+        assert_eq!(sheet_name("'Q3' sales: East/West'", 0), "Q3' sales EastWest 1");
+        assert_eq!(sheet_name(" ' ", 1), "Table 2");
+        assert_eq!(sheet_name("A very long table title that goes on", 2), "A very long table title t 3");
+        let quoted = TableGrid {
+            title: "'Totals'".to_string(),
+            cells: vec![vec!["1".to_string()]],
+            ..Default::default()
+        };
+        assert!(to_xlsx(&[quoted]).is_ok());
+    }
+
+    #[test]
+    fn test_only_exact_numbers_are_written_as_numbers() {
+        assert_eq!(xlsx_number("10"), Some(10.0));
+        assert_eq!(xlsx_number("-3.25"), Some(-3.25));
+        assert_eq!(xlsx_number("1.50"), Some(1.5));
+        assert_eq!(xlsx_number("0.05"), Some(0.05));
+        assert_eq!(xlsx_number("007"), None);
+        assert_eq!(xlsx_number("1e5"), None);
+        assert_eq!(xlsx_number("12345678901234567890"), None);
+        assert_eq!(xlsx_number("1234567890123456"), None);
+        assert_eq!(xlsx_number(".5"), None);
+        assert_eq!(xlsx_number("+5"), None);
+        assert_eq!(xlsx_number("inf"), None);
+    }
+
+    #[test]
+    fn test_xlsx_size_limits() {
+        let wide = TableGrid {
+            cells: vec![vec![String::new(); XLSX_MAX_COLUMNS + 1]],
+            ..Default::default()
+        };
+        assert!(matches!(to_xlsx(&[wide]), Err(ProcessorError::ExportError(_))));
+    }
+
+    #[test]
+    fn test_huge_spans_are_clamped() {
+        let table: Table = serde_json
+            ::from_str(r#"{"rows": [[{"text": "a", "row_span": 1e18, "col_span": 1e18}], [{"text": "b"}, {"text": "c"}]]}"#)
+            .unwrap();
+        let grid = table.grid();
+        assert_eq!(grid.cells.len(), 2);
+        assert_eq!(grid.columns(), MAX_SPAN + 2);
+        assert_eq!(grid.merges, vec![Merge { row: 0, column: 0, row_span: 2, col_span: MAX_SPAN }]);
+    }
+
+    #[test]
+    fn test_overlapping_spans_are_cut() {
+        let table = Table {
+            rows: vec![
+                vec![cell("a", 1, 1), cell("b", 2, 1)],
+                vec![cell("c", 1, 3)]
+            ],
+            ..Default::default()
+        };
+        let grid = table.grid();
+        assert_eq!(grid.cells[1], vec!["c", ""]);
+        assert_eq!(grid.merges, vec![Merge { row: 0, column: 1, row_span: 2, col_span: 1 }]);
+    }
+
+    #[test]
+    fn test_lenient_spans() {
+        let table: Table = serde_json
+            ::from_str(r#"{"header_rows": 1.0, "rows": [[{"text": "a", "row_span": 0, "col_span": 2.0}]]}"#)
+            .unwrap();
+        assert_eq!(table.header_rows, 1);
+        assert_eq!(table.rows[0][0].row_span, 1);
+        assert_eq!(table.rows[0][0].col_span, 2);
+    }
+}
@@ -0,0 +1,736 @@
+use crate::{ errors::ProcessorError, prompts::{ AnalysisConfig, ContentCategory } };
+use base64::Engine;
+use serde::Deserialize;
+use std::{ collections::HashMap, fs, path::Path };
+
+/// Version reported for prompts compiled into the crate.
+pub const BUILTIN_VERSION: &str = "builtin";
+
+/// Names of the prompts that can be replaced by a template.
+pub const TEMPLATE_NAMES: [&str; 13] = [
+    "concise",
+    "detailed",
+    "json",
+    "list",
+    "category_specific",
+    "custom",
+    "discovery",
+    "platform_specific",
+    "schema_guided",
+    "accessibility",
+    "ocr",
+    "comparison",
+    "question",
+];
+
+// Guards against partials that include each other
+const MAX_PARTIAL_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Variable(String),
+    Condition {
+        name: String,
+        negate: bool,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Partial(String),
+}
+
+/// A parsed prompt template.
+///
+/// Supports `{{variable}}`, `{{#if name}}...{{else}}...{{/if}}`, `{{#unless name}}...{{/unless}}`,
+/// `{{> partial}}` and `{{! comments }}`. Conditions test `AnalysisConfig` toggles by field
+/// name, or whether a variable is non-empty. Block tags on a line of their own don't leave
+/// an empty line behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    name: String,
+    version: String,
+    nodes: Vec<Node>,
+}
+
+/// Values and flags available while rendering a template.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    variables: HashMap<String, String>,
+    flags: HashMap<String, bool>,
+}
+
+/// An image with the response expected for it, sent as prior conversation turns so the
+/// model sees the output wanted before the image to analyze.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FewShotExample {
+    /// Prompt the example applies to, e.g. `json`; the `json` and `schema_guided`
+    /// prompts when `None`, as other prompts don't answer in the same format
+    pub template: Option<String>,
+    /// Category the example applies to, e.g. `parking_ticket`; any category when `None`
+    pub category: Option<String>,
+    /// Base64-encoded image
+    pub image: String,
+    pub response: String,
+}
+
+/// Templates, partials and few-shot examples loaded from TOML files.
+///
+/// ```toml
+/// [templates.json]
+/// version = "2024-06-01"
+/// template = """
+/// {{> json_rules}}
+/// {{#if extract_text}}Transcribe every visible word.{{/if}}
+/// {{structure}}
+/// """
+///
+/// [partials]
+/// tone = "Be factual."
+///
+/// [[examples]]
+/// category = "parking_ticket"
+/// image = "examples/ticket.jpg"
+/// response = '{"classification": {"primary_category": "parking_ticket", "confidence": 0.95}}'
+/// ```
+#[derive(Debug, Clone)]
+pub struct PromptRegistry {
+    templates: HashMap<String, Template>,
+    partials: HashMap<String, Vec<Node>>,
+    examples: Vec<FewShotExample>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    #[serde(default)]
+    templates: HashMap<String, TemplateEntry>,
+    #[serde(default)]
+    partials: HashMap<String, String>,
+    #[serde(default)]
+    examples: Vec<ExampleEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExampleEntry {
+    template: Option<String>,
+    category: Option<String>,
+    /// Path of the image, relative to the file
+    image: String,
+    response: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateEntry {
+    version: Option<String>,
+    template: String,
+}
+
+fn template_error(message: String) -> ProcessorError {
+    ProcessorError::TemplateError(message)
+}
+
+impl Template {
+    /// Parses a template. Without a version, a hash of the source is used.
+    pub fn parse(name: &str, version: Option<&str>, source: &str) -> Result<Self, ProcessorError> {
+        Ok(Self {
+            name: name.to_string(),
+            version: version
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{:016x}", fnv1a(source))),
+            nodes: parse_nodes(name, source)?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// `name@version`, attached to results produced with this template.
+    pub fn id(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+}
+
+impl FewShotExample {
+    /// Creates an example for any prompt and category; narrow it with
+    /// [`FewShotExample::for_template`] and [`FewShotExample::for_category`].
+    pub fn new(image_data: &[u8], response: &str) -> Result<Self, ProcessorError> {
+        image
+            ::guess_format(image_data)
+            .map_err(|e| template_error(format!("Example image is not a supported image: {}", e)))?;
+        Ok(Self {
+            template: None,
+            category: None,
+            image: base64::engine::general_purpose::STANDARD.encode(image_data),
+            response: response.trim().to_string(),
+        })
+    }
+
+    pub fn for_template(mut self, template: &str) -> Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    pub fn for_category(mut self, category: &str) -> Self {
+        self.category = Some(category_key(category));
+        self
+    }
+
+    fn applies_to(&self, template: &str, category: Option<&ContentCategory>) -> bool {
+        // Schema-guided prompts ask for the same JSON as the `json` prompt
+        let template_matches = match self.template.as_deref().unwrap_or("json") {
+            "json" => template == "json" || template == "schema_guided",
+            t => t == template,
+        };
+        let category_matches = match (&self.category, category) {
+            (None, _) => true,
+            (Some(name), Some(category)) => *name == content_category_key(category),
+            (Some(_), None) => false,
+        };
+        template_matches && category_matches
+    }
+}
+
+/// Appends a hash of the examples to a prompt version, so changing an example changes
+/// the version reported with results. Versions without examples are left as they are.
+pub fn with_examples_version(version: String, examples: &[&FewShotExample]) -> String {
+    if examples.is_empty() {
+        return version;
+    }
+    let source = examples
+        .iter()
+        .map(|example| {
+            format!(
+                "{}\0{}\0{}\0{}",
+                example.template.as_deref().unwrap_or_default(),
+                example.category.as_deref().unwrap_or_default(),
+                example.image,
+                example.response
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\0");
+    format!("{}+examples@{:016x}", version, fnv1a(&source))
+}
+
+fn category_key(name: &str) -> String {
+    name.trim().to_lowercase().replace([' ', '-'], "_")
+}
+
+fn content_category_key(category: &ContentCategory) -> String {
+    match category {
+        ContentCategory::Discovered { name, .. } => category_key(name),
+        ContentCategory::Screenshot { .. } => "screenshot".to_string(),
+        other => category_key(&other.label()),
+    }
+}
+
+impl TemplateContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_variable(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.variables.insert(name.to_string(), value.into());
+        self
+    }
+
+    pub fn with_flag(mut self, name: &str, value: bool) -> Self {
+        self.flags.insert(name.to_string(), value);
+        self
+    }
+
+    /// Adds every `AnalysisConfig` toggle as a flag.
+    pub fn with_config(mut self, config: &AnalysisConfig) -> Self {
+        for name in AnalysisConfig::toggle_names() {
+            if let Some(enabled) = config.enabled(name) {
+                self.flags.insert(name.to_string(), enabled);
+            }
+        }
+        self
+    }
+
+    fn condition(&self, name: &str) -> Option<bool> {
+        self.flags
+            .get(name)
+            .copied()
+            .or_else(|| self.variables.get(name).map(|v| !v.trim().is_empty()))
+    }
+}
+
+impl Default for PromptRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PromptRegistry {
+    /// A registry without templates, holding the built-in partials `json_rules`,
+    /// `json_data_types`, `json_color_codes` and `json_reminder`.
+    pub fn new() -> Self {
+        let partials = crate::prompts
+            ::builtin_partials()
+            .into_iter()
+            .map(|(name, text)| (name.to_string(), vec![Node::Text(text.to_string())]))
+            .collect();
+        Self { templates: HashMap::new(), partials, examples: Vec::new() }
+    }
+
+    /// Loads templates and partials from a single TOML document. Example images are
+    /// read relative to the current directory.
+    pub fn from_toml(source: &str) -> Result<Self, ProcessorError> {
+        let mut registry = Self::new();
+        let mut defined = Vec::new();
+        registry.add_toml(source, "registry", Path::new(""), &mut defined)?;
+        registry.validate()?;
+        Ok(registry)
+    }
+
+    /// Loads every `*.toml` file in `dir`, in file name order. A template or partial may
+    /// only be defined once across files; the built-in partials can be overridden.
+    /// Example images are read relative to `dir`.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Self, ProcessorError> {
+        let dir = dir.as_ref();
+        let read_error = |e: std::io::Error| {
+            template_error(format!("Failed to read prompts from {}: {}", dir.display(), e))
+        };
+        let mut paths: Vec<_> = fs
+            ::read_dir(dir)
+            .map_err(read_error)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+
+        let mut registry = Self::new();
+        let mut defined = Vec::new();
+        for path in paths {
+            let source = fs::read_to_string(&path).map_err(read_error)?;
+            registry.add_toml(&source, &path.display().to_string(), dir, &mut defined)?;
+        }
+        registry.validate()?;
+        Ok(registry)
+    }
+
+    fn add_toml(
+        &mut self,
+        source: &str,
+        origin: &str,
+        base: &Path,
+        defined: &mut Vec<String>
+    ) -> Result<(), ProcessorError> {
+        let file: RegistryFile = toml
+            ::from_str(source)
+            .map_err(|e| template_error(format!("{}: {}", origin, e)))?;
+
+        for (name, text) in file.partials {
+            let key = format!("partial {}", name);
+            if defined.contains(&key) {
+                return Err(template_error(format!("{}: {} is defined twice", origin, key)));
+            }
+            self.partials.insert(name.clone(), parse_nodes(&name, &text)?);
+            defined.push(key);
+        }
+        for (name, entry) in file.templates {
+            if !TEMPLATE_NAMES.contains(&name.as_str()) {
+                return Err(
+                    template_error(
+                        format!(
+                            "{}: unknown template {:?}, expected one of: {}",
+                            origin,
+                            name,
+                            TEMPLATE_NAMES.join(", ")
+                        )
+                    )
+                );
+            }
+            let key = format!("template {}", name);
+            if defined.contains(&key) {
+                return Err(template_error(format!("{}: {} is defined twice", origin, key)));
+            }
+            let template = Template::parse(&name, entry.version.as_deref(), &entry.template)?;
+            self.templates.insert(name, template);
+            defined.push(key);
+        }
+        for entry in file.examples {
+            if let Some(name) = entry.template.as_deref() {
+                if !TEMPLATE_NAMES.contains(&name) {
+                    return Err(
+                        template_error(format!("{}: example for unknown template {:?}", origin, name))
+                    );
+                }
+            }
+            let path = base.join(&entry.image);
+            let data = fs
+                ::read(&path)
+                .map_err(|e| {
+                    template_error(format!("{}: failed to read example {}: {}", origin, path.display(), e))
+                })?;
+            let mut example = FewShotExample::new(&data, &entry.response).map_err(|e| match e {
+                ProcessorError::TemplateError(message) =>
+                    template_error(format!("{}: example {}: {}", origin, path.display(), message)),
+                other => other,
+            })?;
+            example.template = entry.template;
+            example.category = entry.category.as_deref().map(category_key);
+            self.examples.push(example);
+        }
+        Ok(())
+    }
+
+    /// Adds an example, after those loaded from files.
+    pub fn with_example(mut self, example: FewShotExample) -> Self {
+        self.examples.push(example);
+        self
+    }
+
+    /// Examples to send before the image when using the named prompt for `category`,
+    /// in the order they were defined.
+    pub fn examples(&self, template: &str, category: Option<&ContentCategory>) -> Vec<&FewShotExample> {
+        self.examples
+            .iter()
+            .filter(|example| example.applies_to(template, category))
+            .collect()
+    }
+
+    // Every referenced partial must exist
+    fn validate(&self) -> Result<(), ProcessorError> {
+        fn check(nodes: &[Node], owner: &str, partials: &HashMap<String, Vec<Node>>) -> Result<(), ProcessorError> {
+            for node in nodes {
+                match node {
+                    Node::Partial(name) if !partials.contains_key(name) => {
+                        return Err(template_error(format!("{}: unknown partial {:?}", owner, name)));
+                    }
+                    Node::Condition { then, otherwise, .. } => {
+                        check(then, owner, partials)?;
+                        check(otherwise, owner, partials)?;
+                    }
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+        for template in self.templates.values() {
+            check(&template.nodes, &template.name, &self.partials)?;
+        }
+        for (name, nodes) in &self.partials {
+            check(nodes, name, &self.partials)?;
+        }
+        Ok(())
+    }
+
+    pub fn template(&self, name: &str) -> Option<&Template> {
+        self.templates.get(name)
+    }
+
+    /// Renders the named template, returning the text and the template id, or `None`
+    /// when the registry has no such template.
+    pub fn render(
+        &self,
+        name: &str,
+        context: &TemplateContext
+    ) -> Result<Option<(String, String)>, ProcessorError> {
+        let Some(template) = self.templates.get(name) else {
+            return Ok(None);
+        };
+        let mut out = String::new();
+        self.render_nodes(&template.nodes, context, name, 0, &mut out)?;
+        Ok(Some((out.trim().to_string(), template.id())))
+    }
+
+    fn render_nodes(
+        &self,
+        nodes: &[Node],
+        context: &TemplateContext,
+        template: &str,
+        depth: usize,
+        out: &mut String
+    ) -> Result<(), ProcessorError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Variable(name) => {
+                    let value = context.variables.get(name).ok_or_else(|| {
+                        template_error(format!("{}: unknown variable {:?}", template, name))
+                    })?;
+                    out.push_str(value);
+                }
+                Node::Condition { name, negate, then, otherwise } => {
+                    let value = context.condition(name).ok_or_else(|| {
+                        template_error(format!("{}: unknown condition {:?}", template, name))
+                    })?;
+                    let branch = if value != *negate { then } else { otherwise };
+                    self.render_nodes(branch, context, template, depth, out)?;
+                }
+                Node::Partial(name) => {
+                    if depth >= MAX_PARTIAL_DEPTH {
+                        return Err(
+                            template_error(format!("{}: partials nested too deeply at {:?}", template, name))
+                        );
+                    }
+                    let partial = self.partials
+                        .get(name)
+                        .ok_or_else(|| template_error(format!("{}: unknown partial {:?}", template, name)))?;
+                    self.render_nodes(partial, context, template, depth + 1, out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Frame {
+    condition: Option<(String, bool)>,
+    then: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+impl Frame {
+    fn nodes(&mut self) -> &mut Vec<Node> {
+        self.otherwise.as_mut().unwrap_or(&mut self.then)
+    }
+}
+
+fn parse_nodes(name: &str, source: &str) -> Result<Vec<Node>, ProcessorError> {
+    let error = |message: &str| template_error(format!("{}: {}", name, message));
+    let is_blank = |s: &str| s.chars().all(|c| c == ' ' || c == '\t' || c == '\r');
+
+    let mut stack = vec![Frame { condition: None, then: Vec::new(), otherwise: None }];
+    let mut rest = source;
+    let mut at_line_start = true;
+
+    while let Some(open) = rest.find("{{") {
+        let close = rest[open..]
+            .find("}}")
+            .map(|i| open + i)
+            .ok_or_else(|| error("unclosed {{"))?;
+        let tag = rest[open + 2..close].trim();
+        let mut text = &rest[..open];
+        let mut after = &rest[close + 2..];
+
+        // Drop the line of a block tag that stands alone on it
+        let block = tag.starts_with(['#', '/', '!']) || tag == "else";
+        if block {
+            let line_begin = text.rfind('\n').map(|i| i + 1);
+            let alone_before = match line_begin {
+                Some(i) => is_blank(&text[i..]),
+                None => at_line_start && is_blank(text),
+            };
+            let line_end = after.find('\n');
+            let alone_after = is_blank(&after[..line_end.unwrap_or(after.len())]);
+            if alone_before && alone_after {
+                text = &text[..line_begin.unwrap_or(0)];
+                after = line_end.map_or("", |i| &after[i + 1..]);
+            }
+        }
+
+        let frame = stack.last_mut().expect("the root frame is never popped");
+        if !text.is_empty() {
+            frame.nodes().push(Node::Text(text.to_string()));
+        }
+
+        if tag.starts_with('!') {
+            // Comment
+        } else if let Some(condition) = tag.strip_prefix("#if ") {
+            stack.push(Frame {
+                condition: Some((identifier(condition.trim()).map_err(|e| error(&e))?, false)),
+                then: Vec::new(),
+                otherwise: None,
+            });
+        } else if let Some(condition) = tag.strip_prefix("#unless ") {
+            stack.push(Frame {
+                condition: Some((identifier(condition.trim()).map_err(|e| error(&e))?, true)),
+                then: Vec::new(),
+                otherwise: None,
+            });
+        } else if tag == "else" {
+            if frame.condition.is_none() || frame.otherwise.is_some() {
+                return Err(error("{{else}} outside of {{#if}} or {{#unless}}"));
+            }
+            frame.otherwise = Some(Vec::new());
+        } else if tag == "/if" || tag == "/unless" {
+            let negate = tag == "/unless";
+            match stack.pop() {
+                Some(Frame { condition: Some((name, n)), then, otherwise }) if n == negate => {
+                    let node = Node::Condition {
+                        name,
+                        negate,
+                        then,
+                        otherwise: otherwise.unwrap_or_default(),
+                    };
+                    stack.last_mut().ok_or_else(|| error("unbalanced block"))?.nodes().push(node);
+                }
+                _ => {
+                    return Err(error(&format!("{{{{{}}}}} does not close an open block", tag)));
+                }
+            }
+        } else if let Some(partial) = tag.strip_prefix('>') {
+            let partial = identifier(partial.trim()).map_err(|e| error(&e))?;
+            frame.nodes().push(Node::Partial(partial));
+        } else {
+            let variable = identifier(tag).map_err(|e| error(&e))?;
+            frame.nodes().push(Node::Variable(variable));
+        }
+
+        at_line_start = rest[..rest.len() - after.len()].ends_with('\n');
+        rest = after;
+    }
+
+    if stack.len() > 1 {
+        return Err(error("block is not closed"));
+    }
+    let mut root = stack.pop().expect("the root frame is never popped");
+    if !rest.is_empty() {
+        root.then.push(Node::Text(rest.to_string()));
+    }
+    Ok(root.then)
+}
+
+fn identifier(name: &str) -> Result<String, String> {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Ok(name.to_string())
+    } else {
+        Err(format!("invalid name {:?}", name))
+    }
+}
+
+// Stable across builds, unlike `DefaultHasher`
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ (byte as u64)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGISTRY: &str =
+        r#"
+[templates.concise]
+version = "7"
+template = """
+{{> intro}}
+{{#if extract_text}}
+Read all text.
+{{else}}
+Ignore text.
+{{/if}}
+{{#unless category}}Category unknown.{{/unless}}{{#if category}}It is a {{category}}.{{/if}}
+"""
+
+[partials]
+intro = "Describe the image briefly."
+"#;
+
+    #[test]
+    fn test_render_conditions_and_partials() {
+        let registry = PromptRegistry::from_toml(REGISTRY).unwrap();
+        let mut config = AnalysisConfig::default();
+        let context = TemplateContext::new().with_config(&config).with_variable("category", "receipt");
+        let (text, id) = registry.render("concise", &context).unwrap().unwrap();
+        assert_eq!(text, "Describe the image briefly.\nRead all text.\nIt is a receipt.");
+        assert_eq!(id, "concise@7");
+
+        config.extract_text = false;
+        let context = TemplateContext::new().with_config(&config).with_variable("category", "");
+        let (text, _) = registry.render("concise", &context).unwrap().unwrap();
+        assert_eq!(text, "Describe the image briefly.\nIgnore text.\nCategory unknown.");
+
+        assert!(registry.render("json", &context).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_registries() {
+        let unclosed = "[templates.json]\ntemplate = \"{{#if extract_text}}text\"";
+        assert!(PromptRegistry::from_toml(unclosed).is_err());
+        let unknown_name = "[templates.jsn]\ntemplate = \"x\"";
+        assert!(PromptRegistry::from_toml(unknown_name).is_err());
+        let missing_partial = "[templates.json]\ntemplate = \"{{> nope}}\"";
+        assert!(PromptRegistry::from_toml(missing_partial).is_err());
+    }
+
+    #[test]
+    fn test_unknown_variable_and_builtin_partials() {
+        let registry = PromptRegistry::from_toml(
+            "[templates.json]\ntemplate = \"{{> json_reminder}}\\n{{structur}}\""
+        ).unwrap();
+        assert_eq!(registry.template("json").unwrap().version().len(), 16);
+        match registry.render("json", &TemplateContext::new()) {
+            Err(ProcessorError::TemplateError(message)) => assert!(message.contains("structur")),
+            other => panic!("expected template error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_example_registry_renders() {
+        let registry = PromptRegistry::from_toml(include_str!("../docs/prompts.example.toml")).unwrap();
+        let context = TemplateContext::new()
+            .with_config(&AnalysisConfig::default())
+            .with_variable("structure", "{}")
+            .with_variable("context", "")
+            .with_variable("category", "")
+            .with_variable("builtin", "Write alt text.");
+        let (text, id) = registry.render("json", &context).unwrap().unwrap();
+        assert!(text.starts_with("SYSTEM INSTRUCTION"));
+        assert!(text.contains("never guess prices"));
+        assert_eq!(id, "json@2024-06-01");
+        let (text, _) = registry.render("concise", &context).unwrap().unwrap();
+        assert_eq!(text, "Briefly describe what you see in this image, in two sentences at most.");
+    }
+
+    fn png() -> Vec<u8> {
+        let mut data = std::io::Cursor::new(Vec::new());
+        image::DynamicImage
+            ::new_rgb8(1, 1)
+            .write_to(&mut data, image::ImageOutputFormat::Png)
+            .unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn test_examples_match_template_and_category() {
+        let ticket = ContentCategory::Discovered {
+            name: "parking_ticket".to_string(),
+            confidence: 0.8,
+            traits: Vec::new(),
+        };
+        let registry = PromptRegistry::new()
+            .with_example(FewShotExample::new(&png(), "{\"a\": 1}").unwrap().for_category("Parking Ticket"))
+            .with_example(FewShotExample::new(&png(), "{\"b\": 2}").unwrap().for_template("json"))
+            .with_example(FewShotExample::new(&png(), "Alt").unwrap().for_template("accessibility"));
+
+        let responses = |template, category| {
+            registry
+                .examples(template, category)
+                .iter()
+                .map(|e| e.response.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(responses("schema_guided", Some(&ticket)), vec!["{\"a\": 1}", "{\"b\": 2}"]);
+        assert_eq!(responses("json", Some(&ContentCategory::Receipt)), vec!["{\"b\": 2}"]);
+        assert_eq!(responses("accessibility", None), vec!["Alt"]);
+        assert!(responses("concise", Some(&ticket)).is_empty());
+
+        assert!(FewShotExample::new(b"not an image", "{}").is_err());
+        let missing = "[[examples]]\ncategory = \"receipt\"\nimage = \"missing.png\"\nresponse = \"{}\"";
+        assert!(PromptRegistry::from_toml(missing).is_err());
+    }
+
+    #[test]
+    fn test_examples_change_the_version() {
+        let first = FewShotExample::new(&png(), "{\"a\": 1}").unwrap();
+        let second = FewShotExample::new(&png(), "{\"a\": 2}").unwrap();
+        assert_eq!(with_examples_version("json@7".to_string(), &[]), "json@7");
+        let version = with_examples_version("json@7".to_string(), &[&first]);
+        assert!(version.starts_with("json@7+examples@"));
+        assert_eq!(version, with_examples_version("json@7".to_string(), &[&first]));
+        assert_ne!(version, with_examples_version("json@7".to_string(), &[&second]));
+    }
+}
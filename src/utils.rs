@@ -1,6 +1,7 @@
 use crate::errors::ProcessorError;
 use image::{DynamicImage, ImageBuffer, Rgb};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 pub fn enhance_image(img: &DynamicImage) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>, ProcessorError> {
     let rgb_image = img.to_rgb8();
@@ -32,3 +33,180 @@ pub fn enhance_image(img: &DynamicImage) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>
 
     Ok(enhanced)
 }
+
+/// A dominant color measured from the image pixels.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DominantColor {
+    pub hex: String,
+    pub rgb: [u8; 3],
+    /// Share of sampled pixels assigned to this color, between 0.0 and 1.0.
+    pub dominance: f32,
+}
+
+/// Longest edge the image is downsampled to before palette extraction.
+const PALETTE_SAMPLE_SIZE: u32 = 128;
+
+/// Extracts up to `max_colors` dominant colors using median-cut quantization.
+///
+/// Results are sorted by dominance, most dominant first.
+pub fn extract_palette(img: &DynamicImage, max_colors: usize) -> Vec<DominantColor> {
+    if max_colors == 0 || img.width() == 0 || img.height() == 0 {
+        return Vec::new();
+    }
+
+    let sample = if img.width() > PALETTE_SAMPLE_SIZE || img.height() > PALETTE_SAMPLE_SIZE {
+        img.thumbnail(PALETTE_SAMPLE_SIZE, PALETTE_SAMPLE_SIZE).to_rgb8()
+    } else {
+        img.to_rgb8()
+    };
+    let pixels: Vec<[u8; 3]> = sample.pixels().map(|p| p.0).collect();
+    let total = pixels.len() as f32;
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < max_colors {
+        // Split the box with the widest channel range that still has pixels to divide
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (channel, range) = widest_channel(b);
+                (i, channel, range)
+            })
+            .filter(|(_, _, range)| *range > 0)
+            .max_by_key(|(_, _, range)| *range)
+            .map(|(i, channel, _)| (i, channel))
+        else {
+            break;
+        };
+
+        let mut bucket = boxes.swap_remove(index);
+        bucket.par_sort_unstable_by_key(|p| p[channel]);
+        // Cut at the median, moved to a value boundary so equal colors stay together
+        let pivot = bucket[bucket.len() / 2][channel];
+        let mut split = bucket.partition_point(|p| p[channel] < pivot);
+        if split == 0 {
+            split = bucket.partition_point(|p| p[channel] <= pivot);
+        }
+        let upper = bucket.split_off(split);
+        boxes.push(bucket);
+        boxes.push(upper);
+    }
+
+    let mut palette: Vec<DominantColor> = boxes
+        .par_iter()
+        .filter(|b| !b.is_empty())
+        .map(|b| {
+            let mut sum = [0u64; 3];
+            for p in b {
+                for c in 0..3 {
+                    sum[c] += p[c] as u64;
+                }
+            }
+            let n = b.len() as u64;
+            let rgb = [(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8];
+            DominantColor {
+                hex: format!("#{:02X}{:02X}{:02X}", rgb[0], rgb[1], rgb[2]),
+                rgb,
+                dominance: b.len() as f32 / total,
+            }
+        })
+        .collect();
+
+    palette.sort_by(|a, b| b.dominance.total_cmp(&a.dominance));
+    palette
+}
+
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    let mut min = [u8::MAX; 3];
+    let mut max = [u8::MIN; 3];
+    for p in pixels {
+        for c in 0..3 {
+            min[c] = min[c].min(p[c]);
+            max[c] = max[c].max(p[c]);
+        }
+    }
+    (0..3)
+        .map(|c| (c, max[c] - min[c]))
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+/// A box in fractions of the image width and height.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BoundingBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl BoundingBox {
+    /// Moves the box inside the image, shrinking it where it sticks out.
+    pub fn clamp(self) -> Self {
+        let x = self.x.clamp(0.0, 1.0);
+        let y = self.y.clamp(0.0, 1.0);
+        Self {
+            x,
+            y,
+            width: self.width.clamp(0.0, 1.0 - x),
+            height: self.height.clamp(0.0, 1.0 - y),
+        }
+    }
+
+    /// Smallest box enclosing all boxes, or an empty box when there are none.
+    pub fn union(boxes: impl Iterator<Item = BoundingBox>) -> Self {
+        let (mut left, mut top, mut right, mut bottom) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for b in boxes {
+            left = left.min(b.x);
+            top = top.min(b.y);
+            right = right.max(b.x + b.width);
+            bottom = bottom.max(b.y + b.height);
+        }
+        if left > right {
+            return Self::default();
+        }
+        Self { x: left, y: top, width: right - left, height: bottom - top }
+    }
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escapes text for XML and HTML content and double-quoted attributes.
+pub fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_palette_two_colors() {
+        let img = DynamicImage::ImageRgb8(
+            ImageBuffer::from_fn(10, 10, |x, _| {
+                if x < 7 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) }
+            })
+        );
+        let palette = extract_palette(&img, 4);
+        assert_eq!(palette.len(), 2);
+        assert_eq!(palette[0].hex, "#FF0000");
+        assert!((palette[0].dominance - 0.7).abs() < 0.01);
+        assert_eq!(palette[1].hex, "#0000FF");
+    }
+
+    #[test]
+    fn test_extract_palette_empty() {
+        let img = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(4, 4, Rgb([1, 2, 3])));
+        assert!(extract_palette(&img, 0).is_empty());
+        assert_eq!(extract_palette(&img, 3).len(), 1);
+    }
+}
@@ -1,18 +1,137 @@
 use axum::{
-    extract::{ Multipart, Query },
+    extract::{
+        rejection::QueryRejection,
+        FromRequest,
+        FromRequestParts,
+        Multipart,
+        Path as UrlPath,
+        Query,
+        Request,
+        State,
+    },
     response::{ Html, Json },
-    routing::{ get, post },
+    routing::{ delete, get, post },
     Router,
-    http::StatusCode,
+    http::{ header, request::Parts, StatusCode },
 };
-use serde::{ Serialize, Deserialize };
-use std::net::SocketAddr;
+use async_trait::async_trait;
+use serde::{ de::DeserializeOwned, Serialize, Deserialize };
+use std::{ net::SocketAddr, path::{ Component, Path, PathBuf }, sync::Arc, time::Duration };
 use tokio::fs;
 use tower_http::{ services::ServeDir, cors::CorsLayer, limit::RequestBodyLimitLayer };
 use tracing::{ info, warn, error, debug, Level };
 use tracing_subscriber::FmtSubscriber;
-use eyeris::{ AIProvider, ImageProcessor, TokenUsage };
+use eyeris::{
+    categories::CATEGORY_NAMES,
+    fetch::{ AllowedHost, FetchBudget },
+    language,
+    sessions::{ DEFAULT_MAX_SESSIONS, DEFAULT_SESSION_TTL },
+    AccessibilityDescription,
+    AIProvider,
+    AnalysisConfig,
+    AnalysisResult,
+    Answer,
+    AnswerType,
+    CategoryExtraction,
+    ChartExtraction,
+    Comparison,
+    ContentCategory,
+    Conversation,
+    Detections,
+    DiagramExtraction,
+    DocumentExtraction,
+    GenerationParams,
+    ImageFetcher,
+    ImageProcessor,
+    InterfaceDetails,
+    Preprocessing,
+    PromptFormat,
+    PromptRegistry,
+    Region,
+    RepairReport,
+    SessionStore,
+    Table,
+    TableGrid,
+    TokenUsage,
+};
 use axum::response::IntoResponse;
+use base64::Engine;
+use futures::{ stream, StreamExt };
+
+/// Shared by all handlers.
+#[derive(Clone)]
+struct AppState {
+    /// Templates replacing the built-in prompts, loaded from `PROMPTS_DIR` at startup
+    prompts: Option<Arc<PromptRegistry>>,
+    /// Analyses that can be asked follow-up questions
+    sessions: Arc<SessionStore<Arc<tokio::sync::Mutex<Session>>>>,
+    /// Directory JSON requests can reference images in, from `LOCAL_IMAGES_DIR`
+    local_images: Option<Arc<PathBuf>>,
+    /// Downloads images sent as `image_url`
+    fetcher: Arc<ImageFetcher>,
+}
+
+/// An analysis kept for follow-up questions, with the model that produced it.
+#[derive(Debug, Clone)]
+struct Session {
+    conversation: Conversation,
+    provider: AIProvider,
+    model: Option<String>,
+    params: GenerationParams,
+}
+
+/// How often expired sessions are dropped.
+const SESSION_EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Body of a JSON analysis request.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnalyzeRequest {
+    /// Base64-encoded image or data URL
+    image: Option<String>,
+    /// URL of an image the server downloads, after `image`
+    image_url: Option<String>,
+    /// Images analyzed together, after `image` and `image_url`
+    #[serde(default)]
+    images: Vec<JsonImage>,
+    /// Overrides of the query parameters, as in the `options` form field
+    options: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// An image of a JSON request, given as `data`, `url` or `path`.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonImage {
+    /// Base64-encoded image or data URL
+    data: Option<String>,
+    /// URL the server downloads the image from
+    url: Option<String>,
+    /// Path relative to `LOCAL_IMAGES_DIR`
+    path: Option<String>,
+    /// Name used for the image in exports, e.g. COCO
+    file_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AskRequest {
+    question: String,
+}
+
+#[derive(Debug, Serialize)]
+struct AskImageResponse {
+    #[serde(flatten)]
+    answer: Answer,
+    token_usage: TokenUsage,
+}
+
+#[derive(Debug, Serialize)]
+struct AskResponse {
+    session_id: String,
+    answer: String,
+    /// Follow-up questions answered in this session so far
+    questions: usize,
+    token_usage: TokenUsage,
+}
 
 #[derive(Debug, Serialize)]
 struct ApiResponse<T> {
@@ -25,22 +144,508 @@ struct ApiResponse<T> {
 #[derive(Debug, Serialize)]
 struct AnalysisResponse {
     analysis: String,
+    /// Parsed and validated form of `analysis`, absent when it doesn't match the schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<AnalysisResult>,
+    /// Why `analysis` or its category details could not be parsed, absent when they could
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    violations: Vec<String>,
+    /// Fixes applied to make the model output parse, absent when none were needed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repairs: Option<RepairReport>,
+    /// Category the analysis targeted, given by the caller or detected by two-stage analysis
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<ContentCategory>,
+    /// `builtin`, or the `name@version` of the prompt template used
+    prompt_version: String,
+    /// Id for follow-up questions at `/api/v1/sessions/{id}/ask`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+    #[serde(skip)]
+    session: Option<Session>,
+    /// Typed fields and arithmetic checks for receipts, invoices, cards, forms and IDs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    document: Option<DocumentExtraction>,
+    /// Axes, series and data points for charts, graphs and dashboards
+    #[serde(skip_serializing_if = "Option::is_none")]
+    charts: Option<ChartExtraction>,
+    /// Nodes and edges for flowcharts, diagrams and mind maps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diagram: Option<DiagramExtraction>,
+    /// Application, platform and component listing for screenshots and user interfaces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interface: Option<InterfaceDetails>,
+    /// Labeled bounding boxes in pixels, when object detection was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detections: Option<Detections>,
     token_usage: Option<TokenUsage>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Accessibility text for one uploaded image.
+#[derive(Debug, Serialize)]
+struct AccessibilityItem {
+    file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<AccessibilityDescription>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_usage: Option<TokenUsage>,
+}
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY_SIZE: usize = 100 * 1024 * 1024;
+
+/// Most images accepted by one accessibility request.
+const MAX_ACCESSIBILITY_IMAGES: usize = 100;
+
+/// Images analyzed together by one analysis request.
+const MAX_ANALYSIS_IMAGES: usize = 10;
+
+/// Most continuation requests one request may ask for, as each is a full model call.
+const MAX_CONTINUATIONS: usize = 3;
+
+/// Images described at the same time by one accessibility request.
+const ACCESSIBILITY_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Serialize, Deserialize)]
 struct AnalysisOptions {
-    #[serde(default = "default_model")]
-    model: String,
+    /// `openai` (default) or `ollama`
+    provider: Option<String>,
+    /// Model of the provider; the provider's default when unset
+    model: Option<String>,
+    /// `json` (default) or another `PromptFormat` name
+    prompt_format: Option<String>,
+    /// Region to analyze, as `x,y,width,height`
+    crop: Option<String>,
+    /// Regions to redact, as `x,y,width,height` separated by `;`
+    redact: Option<String>,
+    /// `blackout` (default) or `blur`
+    redaction: Option<String>,
+    /// `AnalysisConfig` toggles to switch off
+    skip: Option<ListParam>,
+    /// `AnalysisConfig` toggles to switch on
+    enable: Option<ListParam>,
+    /// Custom traits to assess
+    traits: Option<ListParam>,
+    /// Known content category, e.g. `receipt`
+    category: Option<String>,
+    /// Platform shown in a `screenshot`, e.g. `ios`
+    platform: Option<String>,
+    /// Maximum alt text length in characters
+    alt_text_limit: Option<usize>,
+    /// How often truncated JSON output is sent back to the model to be continued
+    #[serde(default)]
+    max_continuations: usize,
+    /// Whether to use the provider's JSON Schema support when available
+    #[serde(default = "default_native_schema")]
+    native_schema: bool,
+    /// Classify the image first and use a category-specific prompt
+    #[serde(default)]
+    two_stage: bool,
+    /// Model of the same provider for the classification call of `two_stage`; the
+    /// analysis model when unset
+    classifier_model: Option<String>,
+    /// Reproduce tables cell by cell under `extracted_data.tables`
+    #[serde(default)]
+    tables: bool,
+    /// Locate main elements with labels and bounding boxes
+    #[serde(default)]
+    objects: bool,
+    /// Language to write the analysis in, e.g. `es` or `German`
+    language: Option<String>,
+    /// Add the detected language and a translation to extracted text
+    #[serde(default)]
+    translate: bool,
+    /// Sampling temperature, from 0.0 to 2.0
+    temperature: Option<f32>,
+    /// Nucleus sampling probability mass, from 0.0 to 1.0
+    top_p: Option<f32>,
+    /// Maximum tokens to generate
+    max_tokens: Option<u32>,
+    /// Seed for repeatable sampling
+    seed: Option<u64>,
+    /// Stop sequences
+    stop: Option<ListParam>,
+    /// `low`, `high` or `auto`
+    detail: Option<String>,
+}
+
+/// A list given comma-separated in the query string, or as an array in JSON options.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum ListParam {
+    Items(Vec<String>),
+    Joined(String),
+}
+
+impl ListParam {
+    fn items(param: &Option<Self>) -> Vec<String> {
+        match param {
+            Some(Self::Items(items)) =>
+                items
+                    .iter()
+                    .map(|item| item.trim().to_string())
+                    .filter(|item| !item.is_empty())
+                    .collect(),
+            Some(Self::Joined(joined)) => comma_list(Some(joined)),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl AnalysisOptions {
+    /// Overrides options with the keys of the JSON object sent in the `options` form field.
+    fn apply_json(&mut self, text: &str) -> Result<(), String> {
+        let overrides = serde_json
+            ::from_str(text)
+            .map_err(|e| format!("options must be a JSON object: {}", e))?;
+        self.apply_overrides(overrides)
+    }
+
+    fn apply_overrides(
+        &mut self,
+        overrides: serde_json::Map<String, serde_json::Value>
+    ) -> Result<(), String> {
+        let mut merged = match serde_json::to_value(&*self) {
+            Ok(serde_json::Value::Object(merged)) => merged,
+            _ => {
+                return Err("Failed to merge options".to_string());
+            }
+        };
+        for (key, value) in overrides {
+            if !merged.contains_key(&key) {
+                let names: Vec<&str> = merged.keys().map(String::as_str).collect();
+                return Err(unknown_option(&key, &names));
+            }
+            merged.insert(key, value);
+        }
+        *self = serde_json
+            ::from_value(serde_json::Value::Object(merged))
+            .map_err(|e| format!("Invalid options: {}", e))?;
+        Ok(())
+    }
+
+    fn provider(&self) -> Result<AIProvider, String> {
+        match &self.provider {
+            Some(name) => name.parse(),
+            None => Ok(AIProvider::OpenAI),
+        }
+    }
+
+    fn prompt_format(&self) -> Result<PromptFormat, String> {
+        let Some(name) = &self.prompt_format else {
+            return Ok(PromptFormat::default());
+        };
+        let missing = |option: &str| format!("prompt_format {} needs the {} option", name, option);
+        Ok(match name.trim().to_lowercase().as_str() {
+            "concise" => PromptFormat::Concise,
+            "detailed" => PromptFormat::Detailed,
+            "json" => PromptFormat::Json,
+            "list" => PromptFormat::List,
+            "discovery" => PromptFormat::Discovery,
+            "category_specific" =>
+                PromptFormat::CategorySpecific(
+                    self.category.clone().ok_or_else(|| missing("category"))?
+                ),
+            "custom" => {
+                let traits = ListParam::items(&self.traits);
+                if traits.is_empty() {
+                    return Err(missing("traits"));
+                }
+                PromptFormat::Custom(traits)
+            }
+            "platform_specific" =>
+                PromptFormat::PlatformSpecific(
+                    self.platform.clone().ok_or_else(|| missing("platform"))?
+                ),
+            other => {
+                return Err(
+                    format!(
+                        "Unknown prompt format {:?}, expected one of: {}",
+                        other,
+                        PromptFormat::NAMES.join(", ")
+                    )
+                );
+            }
+        })
+    }
+
+    fn preprocessing(&self) -> Result<Preprocessing, String> {
+        let crop = self.crop
+            .as_deref()
+            .map(str::parse::<Region>)
+            .transpose()
+            .map_err(|e| e.to_string())?;
+        let redact = self.redact
+            .as_deref()
+            .unwrap_or("")
+            .split(';')
+            .filter(|r| !r.trim().is_empty())
+            .map(str::parse::<Region>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        let redaction_style = self.redaction
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e: eyeris::ProcessorError| e.to_string())?
+            .unwrap_or_default();
+
+        Ok(Preprocessing { crop, redact, redaction_style })
+    }
+
+    fn processor(&self, state: &AppState) -> Result<ImageProcessor, String> {
+        let format = self.prompt_format()?;
+        let processor = ImageProcessor::new(self.provider()?, self.model.clone(), Some(format))
+            .with_preprocessing(self.preprocessing()?)
+            .with_analysis_config(self.analysis_config()?)
+            .with_generation_params(self.generation_params()?)
+            .with_max_continuations(self.max_continuations()?)
+            .with_native_schema(self.native_schema)
+            .with_two_stage(self.two_stage);
+        let processor = match &self.classifier_model {
+            Some(model) if self.two_stage => processor.with_classifier(self.provider()?, Some(model.clone())),
+            _ => processor,
+        };
+        Ok(match &state.prompts {
+            Some(prompts) => processor.with_prompt_registry(prompts.clone()),
+            None => processor,
+        })
+    }
+
+    fn max_continuations(&self) -> Result<usize, String> {
+        if self.max_continuations > MAX_CONTINUATIONS {
+            return Err(format!(
+                "max_continuations must be at most {}, got {}",
+                MAX_CONTINUATIONS, self.max_continuations
+            ));
+        }
+        Ok(self.max_continuations)
+    }
+
+    fn generation_params(&self) -> Result<GenerationParams, String> {
+        let params = GenerationParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            seed: self.seed,
+            stop: ListParam::items(&self.stop),
+            image_detail: self.detail.as_deref().map(str::parse).transpose()?,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    fn analysis_config(&self) -> Result<AnalysisConfig, String> {
+        let mut config = AnalysisConfig {
+            extract_tables: self.tables,
+            detect_objects: self.objects,
+            translate_text: self.translate,
+            ..Default::default()
+        };
+        for toggle in ListParam::items(&self.enable) {
+            config.set_toggle(&toggle, true)?;
+        }
+        for toggle in ListParam::items(&self.skip) {
+            config.set_toggle(&toggle, false)?;
+        }
+        // The custom prompt format lists the traits itself
+        if !matches!(self.prompt_format()?, PromptFormat::Custom(_)) {
+            config.custom_traits = ListParam::items(&self.traits);
+        }
+        if let Some(language) = &self.language {
+            language::display_name(language)?;
+            config.language = Some(language.trim().to_string());
+        }
+        if let Some(limit) = self.alt_text_limit {
+            config.alt_text_limit = limit;
+        }
+        if let Some(name) = &self.category {
+            let category = ContentCategory::from_name(name).ok_or_else(|| {
+                format!(
+                    "Unknown category {:?}, expected one of: {}",
+                    name,
+                    CATEGORY_NAMES.join(", ")
+                )
+            })?;
+            config.content_category = Some(match category {
+                ContentCategory::Screenshot { .. } => ContentCategory::Screenshot {
+                    platform: self.platform.clone(),
+                },
+                category => category,
+            });
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChartExportOptions {
+    /// `json` (default) or `csv`
+    #[serde(default)]
+    format: ExportFormat,
+    /// Index of the chart to export as CSV when the image contains several
+    #[serde(default)]
+    chart: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct TableExportOptions {
+    /// `json` (default), `csv`, `markdown` or `xlsx`
+    #[serde(default)]
+    format: ExportFormat,
+    /// Index of the only table to export; every table when unset
+    table: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiagramExportOptions {
+    /// `json` (default), `mermaid` or `dot`
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Debug, Deserialize)]
+struct InterfaceExportOptions {
+    /// `json` (default), `tree`, `text` or `html`
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Debug, Deserialize)]
+struct TextExportOptions {
+    /// `json` (default), `text`, `hocr` or `alto`
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Debug, Deserialize)]
+struct ComparisonExportOptions {
+    /// `json` (default), `markdown` or `csv`
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectExportOptions {
+    /// `json` (default), `coco` or `voc`
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// Endpoints without export options.
+#[derive(Debug, Default, Deserialize)]
+struct NoExportOptions {}
+
+/// Query keys of an endpoint's export options, accepted next to the `AnalysisOptions`.
+trait ExportKeys {
+    const KEYS: &'static [&'static str];
+}
+
+impl ExportKeys for NoExportOptions {
+    const KEYS: &'static [&'static str] = &[];
+}
+
+impl ExportKeys for ChartExportOptions {
+    const KEYS: &'static [&'static str] = &["format", "chart"];
+}
+
+impl ExportKeys for TableExportOptions {
+    const KEYS: &'static [&'static str] = &["format", "table"];
+}
+
+impl ExportKeys for DiagramExportOptions {
+    const KEYS: &'static [&'static str] = &["format"];
 }
 
-fn default_model() -> String {
-    "gpt-4o".to_string()
+impl ExportKeys for InterfaceExportOptions {
+    const KEYS: &'static [&'static str] = &["format"];
+}
+
+impl ExportKeys for TextExportOptions {
+    const KEYS: &'static [&'static str] = &["format"];
+}
+
+impl ExportKeys for ComparisonExportOptions {
+    const KEYS: &'static [&'static str] = &["format"];
+}
+
+impl ExportKeys for ObjectExportOptions {
+    const KEYS: &'static [&'static str] = &["format"];
+}
+
+/// `AnalysisOptions` and the endpoint's export options from the query string. Unknown
+/// keys are rejected, as in the `options` form field.
+struct QueryOptions<E = NoExportOptions>(AnalysisOptions, E);
+
+#[async_trait]
+impl<S, E> FromRequestParts<S> for QueryOptions<E> where S: Send + Sync, E: DeserializeOwned + ExportKeys {
+    type Rejection = axum::response::Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let rejection = |e: QueryRejection| error_response(e.body_text());
+        let Query(keys) = Query::<Vec<(String, String)>>
+            ::from_request_parts(parts, state).await
+            .map_err(rejection)?;
+        let Query(options) = Query::<AnalysisOptions>
+            ::from_request_parts(parts, state).await
+            .map_err(rejection)?;
+        let Query(export) = Query::<E>::from_request_parts(parts, state).await.map_err(rejection)?;
+
+        let names = match serde_json::to_value(&options) {
+            Ok(serde_json::Value::Object(fields)) => fields.keys().cloned().collect::<Vec<_>>(),
+            _ => Vec::new(),
+        };
+        let mut names: Vec<&str> = names.iter().map(String::as_str).collect();
+        names.extend(E::KEYS);
+        if let Some((key, _)) = keys.iter().find(|(key, _)| !names.contains(&key.as_str())) {
+            return Err(error_response(unknown_option(key, &names)));
+        }
+        Ok(Self(options, export))
+    }
+}
+
+fn unknown_option(key: &str, names: &[&str]) -> String {
+    format!("Unknown option {:?}, expected one of: {}", key, names.join(", "))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    Markdown,
+    Xlsx,
+    Mermaid,
+    Dot,
+    Tree,
+    Text,
+    Html,
+    Hocr,
+    Alto,
+    Coco,
+    Voc,
+}
+
+fn comma_list(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn default_native_schema() -> bool {
+    true
 }
 
 pub async fn run_server() {
     // Initialize logging first, before any other operations
-    let _subscriber = FmtSubscriber::builder()
+    FmtSubscriber::builder()
         .with_max_level(Level::DEBUG)
         .with_line_number(true)
         .with_file(true)
@@ -61,15 +666,51 @@ pub async fn run_server() {
         .allow_methods(tower_http::cors::Any)
         .allow_headers(tower_http::cors::Any);
 
+    let prompts = load_prompts();
+    let session_ttl = std::env
+        ::var("SESSION_TTL_SECS")
+        .ok()
+        .and_then(|ttl| ttl.parse().ok())
+        .map_or(DEFAULT_SESSION_TTL, Duration::from_secs);
+    let sessions = Arc::new(SessionStore::new(session_ttl, DEFAULT_MAX_SESSIONS));
+    let evicted = sessions.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SESSION_EVICTION_INTERVAL);
+        loop {
+            interval.tick().await;
+            let count = evicted.evict_expired();
+            if count > 0 {
+                debug!("Dropped {} expired sessions", count);
+            }
+        }
+    });
+
     debug!("Setting up routes...");
 
     let app = Router::new()
         .route("/", get(serve_index))
         .route("/api/v1/analyze", post(api_analyze))
+        .route("/api/v1/extract/chart", post(api_extract_chart))
+        .route("/api/v1/extract/tables", post(api_extract_tables))
+        .route("/api/v1/extract/diagram", post(api_extract_diagram))
+        .route("/api/v1/extract/interface", post(api_extract_interface))
+        .route("/api/v1/extract/text", post(api_extract_text))
+        .route("/api/v1/extract/objects", post(api_extract_objects))
+        .route("/api/v1/accessibility", post(api_accessibility))
+        .route("/api/v1/compare", post(api_compare))
+        .route("/api/v1/ask", post(api_ask))
+        .route("/api/v1/sessions/:id/ask", post(api_ask_session))
+        .route("/api/v1/sessions/:id", delete(api_delete_session))
         .route("/api/v1/health", get(health_check))
         .layer(cors)
-        .layer(RequestBodyLimitLayer::new(100 * 1024 * 1024)) // 100MB
-        .nest_service("/assets", ServeDir::new(assets_path));
+        .layer(RequestBodyLimitLayer::new(MAX_BODY_SIZE))
+        .nest_service("/assets", ServeDir::new(assets_path))
+        .with_state(AppState {
+            prompts,
+            sessions,
+            local_images: load_local_images(),
+            fetcher: load_fetcher(),
+        });
 
     let ports = [
         std::env
@@ -114,6 +755,70 @@ pub async fn run_server() {
     }
 }
 
+/// Creates the image URL fetcher, letting the hosts in `IMAGE_URL_ALLOWLIST` through
+/// the private address check. An invalid entry stops the server.
+fn load_fetcher() -> Arc<ImageFetcher> {
+    let allowlist = std::env
+        ::var("IMAGE_URL_ALLOWLIST")
+        .ok()
+        .map(|list| comma_list(Some(&list)))
+        .unwrap_or_default()
+        .iter()
+        .map(|entry| entry.parse::<AllowedHost>())
+        .collect::<Result<Vec<_>, _>>();
+    match allowlist {
+        Ok(allowlist) => {
+            if !allowlist.is_empty() {
+                info!("Allowing image URLs on {:?}", allowlist);
+            }
+            Arc::new(ImageFetcher::new().with_allowlist(allowlist))
+        }
+        Err(e) => {
+            error!("IMAGE_URL_ALLOWLIST: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Resolves `LOCAL_IMAGES_DIR`, the only directory JSON requests may read images from;
+/// local paths are rejected when it is unset. A missing directory stops the server.
+fn load_local_images() -> Option<Arc<PathBuf>> {
+    let dir = std::env::var("LOCAL_IMAGES_DIR").ok()?;
+    match std::fs::canonicalize(&dir) {
+        Ok(dir) if dir.is_dir() => {
+            info!("Serving local images from {}", dir.display());
+            Some(Arc::new(dir))
+        }
+        _ => {
+            error!("LOCAL_IMAGES_DIR {} is not a directory", dir);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Loads prompt templates from `PROMPTS_DIR`, or `./prompts` when it exists. An invalid
+/// registry stops the server rather than silently falling back to the built-in prompts.
+fn load_prompts() -> Option<Arc<PromptRegistry>> {
+    let dir = std::env::var("PROMPTS_DIR").ok();
+    let explicit = dir.is_some();
+    let dir = dir.unwrap_or_else(|| "prompts".to_string());
+    if !explicit && !Path::new(&dir).is_dir() {
+        debug!("No prompts directory, using built-in prompts");
+        return None;
+    }
+
+    match PromptRegistry::load_dir(&dir) {
+        Ok(registry) => {
+            info!("Loaded prompt templates from {}", dir);
+            Some(Arc::new(registry))
+        }
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
 // Web interface handlers
 async fn serve_index() -> Html<String> {
     let index_html = fs
@@ -125,13 +830,36 @@ async fn serve_index() -> Html<String> {
 // API handlers
 #[axum::debug_handler]
 async fn api_analyze(
-    Query(options): Query<AnalysisOptions>,
-    multipart: Multipart
+    State(state): State<AppState>,
+    QueryOptions(options, _): QueryOptions,
+    request: Request
 ) -> impl IntoResponse {
     debug!("Received analyze request with options: {:?}", options);
 
-    match process_image_upload(multipart, options).await {
-        Ok(analysis) => {
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    let result = if is_json {
+        process_json_request(&state, request, options).await
+    } else {
+        match Multipart::from_request(request, &state).await {
+            Ok(multipart) => process_image_upload(&state, multipart, options).await,
+            Err(e) => Err(e.body_text()),
+        }
+    };
+
+    match result {
+        Ok(mut analysis) => {
+            if let Some(session) = analysis.session.take() {
+                let size = session.conversation.size();
+                let session = Arc::new(tokio::sync::Mutex::new(session));
+                analysis.session_id = state.sessions.insert_sized(session, size);
+                if analysis.session_id.is_none() {
+                    warn!("Analysis of {} bytes is too large to keep for follow-up questions", size);
+                }
+            }
             info!("Successfully processed image");
             (
                 StatusCode::OK,
@@ -156,6 +884,685 @@ async fn api_analyze(
     }
 }
 
+async fn api_extract_chart(
+    State(state): State<AppState>,
+    QueryOptions(options, export): QueryOptions<ChartExportOptions>,
+    multipart: Multipart
+) -> impl IntoResponse {
+    debug!("Received chart extraction request with options: {:?} {:?}", options, export);
+
+    // Keep graph or dashboard when the caller asked for them, otherwise treat it as a chart
+    let extraction = match
+        extract_details(&state, multipart, options, "chart", "chart data", |response| {
+            response.charts.filter(|charts| !charts.charts.is_empty())
+        }).await
+    {
+        Ok(extraction) => extraction,
+        Err(response) => {
+            return response;
+        }
+    };
+
+    match export.format {
+        ExportFormat::Json =>
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: true,
+                    message: "Chart extracted successfully".to_string(),
+                    data: Some(extraction),
+                }),
+            ).into_response(),
+        ExportFormat::Csv =>
+            match extraction.charts.get(export.chart) {
+                Some(chart) =>
+                    (
+                        StatusCode::OK,
+                        [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+                        chart.to_csv(),
+                    ).into_response(),
+                None =>
+                    error_response(
+                        format!(
+                            "Chart index {} out of range, the image contains {} charts",
+                            export.chart,
+                            extraction.charts.len()
+                        )
+                    ),
+            }
+        format =>
+            error_response(
+                format!("Format {:?} is not supported for charts, use json or csv", format)
+            ),
+    }
+}
+
+async fn api_extract_tables(
+    State(state): State<AppState>,
+    QueryOptions(mut options, export): QueryOptions<TableExportOptions>,
+    multipart: Multipart
+) -> impl IntoResponse {
+    debug!("Received table extraction request with options: {:?} {:?}", options, export);
+    options.tables = true;
+
+    let tables: Vec<TableGrid> = match process_image_upload(&state, multipart, options).await {
+        Ok(AnalysisResponse { result: Some(result), .. }) =>
+            result.extracted_data.tables.iter().map(Table::grid).collect(),
+        Ok(_) => {
+            return error_response("The analysis could not be parsed into tables".to_string());
+        }
+        Err(e) => {
+            error!("Failed to extract tables: {}", e);
+            return error_response(e);
+        }
+    };
+    if tables.is_empty() {
+        return error_response("No tables found in the image".to_string());
+    }
+    let tables = match export.table {
+        Some(index) if index >= tables.len() => {
+            return error_response(
+                format!("Table index {} out of range, the image contains {} tables", index, tables.len())
+            );
+        }
+        Some(index) => vec![tables[index].clone()],
+        None => tables,
+    };
+
+    match export.format {
+        ExportFormat::Json =>
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: true,
+                    message: "Tables extracted successfully".to_string(),
+                    data: Some(tables),
+                }),
+            ).into_response(),
+        ExportFormat::Csv => {
+            // Tables are separated by an empty line
+            let csv = tables
+                .iter()
+                .map(TableGrid::to_csv)
+                .collect::<Vec<_>>()
+                .join("\n");
+            (StatusCode::OK, [(header::CONTENT_TYPE, "text/csv; charset=utf-8")], csv).into_response()
+        }
+        ExportFormat::Markdown => {
+            let markdown = tables
+                .iter()
+                .map(|table| {
+                    if table.title.is_empty() {
+                        table.to_markdown()
+                    } else {
+                        format!("### {}\n\n{}", table.title, table.to_markdown())
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+                markdown,
+            ).into_response()
+        }
+        ExportFormat::Xlsx =>
+            match eyeris::tables::to_xlsx(&tables) {
+                Ok(bytes) =>
+                    (
+                        StatusCode::OK,
+                        [
+                            (
+                                header::CONTENT_TYPE,
+                                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                            ),
+                            (header::CONTENT_DISPOSITION, "attachment; filename=\"tables.xlsx\""),
+                        ],
+                        bytes,
+                    ).into_response(),
+                Err(e) => {
+                    error!("Failed to export tables: {}", e);
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(ApiResponse::<()> {
+                            success: false,
+                            message: e.to_string(),
+                            data: None,
+                        }),
+                    ).into_response()
+                }
+            }
+        format =>
+            error_response(
+                format!(
+                    "Format {:?} is not supported for tables, use json, csv, markdown or xlsx",
+                    format
+                )
+            ),
+    }
+}
+
+async fn api_extract_diagram(
+    State(state): State<AppState>,
+    QueryOptions(options, export): QueryOptions<DiagramExportOptions>,
+    multipart: Multipart
+) -> impl IntoResponse {
+    debug!("Received diagram extraction request with options: {:?} {:?}", options, export);
+
+    // Keep flowchart or mind map when the caller asked for them, otherwise treat it as a diagram
+    let extraction = match
+        extract_details(&state, multipart, options, "diagram", "diagram", |response| {
+            response.diagram.filter(|diagram| !diagram.diagram.nodes.is_empty())
+        }).await
+    {
+        Ok(extraction) => extraction,
+        Err(response) => {
+            return response;
+        }
+    };
+    if !extraction.issues.is_empty() {
+        warn!("Extracted diagram has issues: {}", extraction.issues.join("; "));
+    }
+
+    match export.format {
+        ExportFormat::Json =>
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: true,
+                    message: "Diagram extracted successfully".to_string(),
+                    data: Some(extraction),
+                }),
+            ).into_response(),
+        ExportFormat::Mermaid =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/vnd.mermaid; charset=utf-8")],
+                extraction.to_mermaid(),
+            ).into_response(),
+        ExportFormat::Dot =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/vnd.graphviz; charset=utf-8")],
+                extraction.to_dot(),
+            ).into_response(),
+        format =>
+            error_response(
+                format!("Format {:?} is not supported for diagrams, use json, mermaid or dot", format)
+            ),
+    }
+}
+
+async fn api_extract_interface(
+    State(state): State<AppState>,
+    QueryOptions(options, export): QueryOptions<InterfaceExportOptions>,
+    multipart: Multipart
+) -> impl IntoResponse {
+    debug!("Received interface extraction request with options: {:?} {:?}", options, export);
+
+    // Keep a platform-specific screenshot when the caller asked for one
+    let details = match
+        extract_details(&state, multipart, options, "user_interface", "interface components", |response| {
+            response.interface.filter(|details| !details.components.is_empty())
+        }).await
+    {
+        Ok(details) => details,
+        Err(response) => {
+            return response;
+        }
+    };
+
+    match export.format {
+        ExportFormat::Json =>
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: true,
+                    message: "Interface extracted successfully".to_string(),
+                    data: Some(details),
+                }),
+            ).into_response(),
+        ExportFormat::Tree =>
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: true,
+                    message: "Interface extracted successfully".to_string(),
+                    data: Some(details.tree()),
+                }),
+            ).into_response(),
+        ExportFormat::Text =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+                details.outline(),
+            ).into_response(),
+        ExportFormat::Html =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+                details.to_html(),
+            ).into_response(),
+        format =>
+            error_response(
+                format!(
+                    "Format {:?} is not supported for interfaces, use json, tree, text or html",
+                    format
+                )
+            ),
+    }
+}
+
+async fn api_extract_text(
+    State(state): State<AppState>,
+    QueryOptions(mut options, export): QueryOptions<TextExportOptions>,
+    mut multipart: Multipart
+) -> impl IntoResponse {
+    debug!("Received text extraction request with options: {:?} {:?}", options, export);
+
+    let result = match read_images(&mut multipart, 1, &mut options, &state.fetcher).await {
+        Ok(images) =>
+            match options.processor(&state) {
+                Ok(processor) => processor.extract_text(&images[0].1).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            }
+        Err(e) => Err(e),
+    };
+    let (layout, token_usage) = match result {
+        Ok(extracted) => extracted,
+        Err(e) => {
+            error!("Failed to extract text: {}", e);
+            return error_response(e);
+        }
+    };
+    info!("Extracted text. Token usage: {:?}", token_usage);
+
+    match export.format {
+        ExportFormat::Json =>
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: true,
+                    message: "Text extracted successfully".to_string(),
+                    data: Some(layout),
+                }),
+            ).into_response(),
+        ExportFormat::Text =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+                layout.to_text(),
+            ).into_response(),
+        ExportFormat::Hocr =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "application/xhtml+xml; charset=utf-8")],
+                layout.to_hocr(),
+            ).into_response(),
+        ExportFormat::Alto =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+                layout.to_alto(),
+            ).into_response(),
+        format =>
+            error_response(
+                format!("Format {:?} is not supported for text, use json, text, hocr or alto", format)
+            ),
+    }
+}
+
+async fn api_extract_objects(
+    State(state): State<AppState>,
+    QueryOptions(mut options, export): QueryOptions<ObjectExportOptions>,
+    multipart: Multipart
+) -> impl IntoResponse {
+    debug!("Received object extraction request with options: {:?} {:?}", options, export);
+    options.objects = true;
+
+    let detections = match process_image_upload(&state, multipart, options).await {
+        Ok(AnalysisResponse { detections: Some(detections), .. }) if !detections.objects.is_empty() => {
+            detections
+        }
+        Ok(_) => {
+            return error_response(
+                "No objects with bounding boxes could be extracted from the image".to_string()
+            );
+        }
+        Err(e) => {
+            error!("Failed to extract objects: {}", e);
+            return error_response(e);
+        }
+    };
+    if !detections.warnings.is_empty() {
+        warn!("Adjusted bounding boxes: {}", detections.warnings.join("; "));
+    }
+
+    // Dataset formats are returned bare so they can be saved as annotation files
+    match export.format {
+        ExportFormat::Json =>
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: true,
+                    message: "Objects extracted successfully".to_string(),
+                    data: Some(detections),
+                }),
+            ).into_response(),
+        ExportFormat::Coco => (StatusCode::OK, Json(detections.to_coco())).into_response(),
+        ExportFormat::Voc => (StatusCode::OK, Json(detections.to_voc())).into_response(),
+        format =>
+            error_response(
+                format!("Format {:?} is not supported for objects, use json, coco or voc", format)
+            ),
+    }
+}
+
+async fn api_accessibility(
+    State(state): State<AppState>,
+    QueryOptions(mut options, _): QueryOptions,
+    mut multipart: Multipart
+) -> impl IntoResponse {
+    debug!("Received accessibility request with options: {:?}", options);
+
+    let images = match read_images(&mut multipart, MAX_ACCESSIBILITY_IMAGES, &mut options, &state.fetcher).await {
+        Ok(images) => images,
+        Err(e) => {
+            return error_response(e);
+        }
+    };
+
+    let processor = match options.processor(&state) {
+        Ok(processor) => processor,
+        Err(e) => {
+            return error_response(e);
+        }
+    };
+
+    let processor = &processor;
+    let items: Vec<AccessibilityItem> = stream
+        ::iter(images)
+        .map(|(file_name, data)| async move {
+            match processor.describe_accessibility(&data).await {
+                Ok((description, token_usage)) =>
+                    AccessibilityItem {
+                        file_name,
+                        description: Some(description),
+                        error: None,
+                        token_usage: Some(token_usage),
+                    },
+                Err(e) => {
+                    error!("Failed to describe {:?}: {}", file_name, e);
+                    AccessibilityItem {
+                        file_name,
+                        description: None,
+                        error: Some(e.to_string()),
+                        token_usage: None,
+                    }
+                }
+            }
+        })
+        .buffered(ACCESSIBILITY_CONCURRENCY)
+        .collect().await;
+
+    let failed = items
+        .iter()
+        .filter(|item| item.error.is_some())
+        .count();
+    (
+        StatusCode::OK,
+        Json(ApiResponse {
+            success: failed < items.len(),
+            message: format!("Described {} of {} images", items.len() - failed, items.len()),
+            data: Some(items),
+        }),
+    ).into_response()
+}
+
+async fn api_compare(
+    State(state): State<AppState>,
+    QueryOptions(mut options, export): QueryOptions<ComparisonExportOptions>,
+    mut multipart: Multipart
+) -> impl IntoResponse {
+    debug!("Received comparison request with options: {:?} {:?}", options, export);
+
+    let result = match read_images(&mut multipart, 2, &mut options, &state.fetcher).await {
+        Ok(images) if images.len() == 2 =>
+            match options.processor(&state) {
+                Ok(processor) =>
+                    processor.compare(&images[0].1, &images[1].1).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            }
+        Ok(_) => Err("Send two image fields, the before image first".to_string()),
+        Err(e) => Err(e),
+    };
+    let (comparison, token_usage): (Comparison, TokenUsage) = match result {
+        Ok(compared) => compared,
+        Err(e) => {
+            error!("Failed to compare images: {}", e);
+            return error_response(e);
+        }
+    };
+    info!(
+        "Compared images, {} differences. Token usage: {:?}",
+        comparison.differences.len(),
+        token_usage
+    );
+
+    match export.format {
+        ExportFormat::Json =>
+            (
+                StatusCode::OK,
+                Json(ApiResponse {
+                    success: true,
+                    message: "Images compared successfully".to_string(),
+                    data: Some(comparison),
+                }),
+            ).into_response(),
+        ExportFormat::Markdown =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+                comparison.to_markdown(),
+            ).into_response(),
+        ExportFormat::Csv =>
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+                comparison.to_csv(),
+            ).into_response(),
+        format =>
+            error_response(
+                format!("Format {:?} is not supported for comparisons, use json, markdown or csv", format)
+            ),
+    }
+}
+
+async fn api_ask(
+    State(state): State<AppState>,
+    QueryOptions(mut options, _): QueryOptions,
+    mut multipart: Multipart
+) -> impl IntoResponse {
+    debug!("Received question with options: {:?}", options);
+
+    let (image, question, answer_type) = match read_question(&mut multipart, &mut options, &state.fetcher).await {
+        Ok(form) => form,
+        Err(e) => {
+            return error_response(e);
+        }
+    };
+    let processor = match options.processor(&state) {
+        Ok(processor) => processor,
+        Err(e) => {
+            return error_response(e);
+        }
+    };
+
+    let (answer, token_usage) = match processor.answer_question(&image, &question, &answer_type).await {
+        Ok(answered) => answered,
+        Err(e) => {
+            error!("Failed to answer question: {}", e);
+            return error_response(e.to_string());
+        }
+    };
+    info!("Answered question. Token usage: {:?}", token_usage);
+
+    (
+        StatusCode::OK,
+        Json(ApiResponse {
+            success: true,
+            message: "Question answered successfully".to_string(),
+            data: Some(AskImageResponse { answer, token_usage }),
+        }),
+    ).into_response()
+}
+
+/// Reads the `image` or `image_url`, `question`, `answer_type`, `values`, `schema` and
+/// `options` fields of a question form, in any order.
+async fn read_question(
+    multipart: &mut Multipart,
+    options: &mut AnalysisOptions,
+    fetcher: &ImageFetcher
+) -> Result<(bytes::Bytes, String, AnswerType), String> {
+    let (mut image, mut question, mut answer_type) = (None, None, None);
+    let (mut values, mut schema) = (None, None);
+    let mut budget = fetcher.budget();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => {
+                break;
+            }
+            Err(e) => {
+                return Err(format!("Failed to read multipart field: {}", e));
+            }
+        };
+        let name = field.name().unwrap_or("").to_string();
+        if name == "image" {
+            image = Some(field.bytes().await.map_err(|e| format!("Failed to read field bytes: {}", e))?);
+            continue;
+        }
+        let text = field.text().await.map_err(|e| format!("Failed to read field {:?}: {}", name, e))?;
+        match name.as_str() {
+            "image_url" => {
+                image = Some(fetch_image(fetcher, &mut budget, &text).await?.1);
+            }
+            "question" => {
+                question = Some(text);
+            }
+            "answer_type" => {
+                answer_type = Some(text);
+            }
+            "values" => {
+                values = Some(text);
+            }
+            "schema" => {
+                schema = Some(
+                    serde_json::from_str(&text).map_err(|e| format!("schema is not valid JSON: {}", e))?
+                );
+            }
+            "options" => {
+                options.apply_json(&text)?;
+            }
+            other => {
+                return Err(
+                    format!(
+                        "Unexpected field {:?}, expected image, image_url, question, answer_type, values, schema or options",
+                        other
+                    )
+                );
+            }
+        }
+    }
+
+    let image = image
+        .filter(|data| !data.is_empty())
+        .ok_or_else(|| "No image found in multipart form".to_string())?;
+    let question = question
+        .filter(|q| !q.trim().is_empty())
+        .ok_or_else(|| "No question found in multipart form".to_string())?;
+    let answer_type = AnswerType::from_name(
+        answer_type.as_deref().unwrap_or("string"),
+        comma_list(values.as_deref()),
+        schema
+    ).map_err(|e| e.to_string())?;
+    Ok((image, question, answer_type))
+}
+
+async fn api_ask_session(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+    Json(request): Json<AskRequest>
+) -> impl IntoResponse {
+    debug!("Received follow-up question for session {}", id);
+
+    let Some(session) = state.sessions.get(&id) else {
+        return session_not_found(&id);
+    };
+    // Questions about one session are answered in turn, so none is lost and the
+    // question limit holds for parallel requests
+    let mut session = session.lock().await;
+    let processor = ImageProcessor::new(session.provider, session.model.clone(), None)
+        .with_generation_params(session.params.clone());
+    let (answer, token_usage) = match processor.ask(&mut session.conversation, &request.question).await {
+        Ok(answered) => answered,
+        Err(e) => {
+            error!("Failed to answer follow-up question: {}", e);
+            return error_response(e.to_string());
+        }
+    };
+    info!("Answered follow-up question. Token usage: {:?}", token_usage);
+
+    let questions = session.conversation.questions();
+    (
+        StatusCode::OK,
+        Json(ApiResponse {
+            success: true,
+            message: "Question answered successfully".to_string(),
+            data: Some(AskResponse { session_id: id, answer, questions, token_usage }),
+        }),
+    ).into_response()
+}
+
+async fn api_delete_session(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>
+) -> impl IntoResponse {
+    if !state.sessions.remove(&id) {
+        return session_not_found(&id);
+    }
+    (
+        StatusCode::OK,
+        Json(ApiResponse::<()> {
+            success: true,
+            message: "Session deleted".to_string(),
+            data: None,
+        }),
+    ).into_response()
+}
+
+fn session_not_found(id: &str) -> axum::response::Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ApiResponse::<()> {
+            success: false,
+            message: format!("Session {} does not exist or has expired", id),
+            data: None,
+        }),
+    ).into_response()
+}
+
+fn error_response(message: String) -> axum::response::Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ApiResponse::<()> {
+            success: false,
+            message,
+            data: None,
+        }),
+    ).into_response()
+}
+
 async fn health_check() -> impl IntoResponse {
     Json(ApiResponse::<()> {
         success: true,
@@ -166,62 +1573,221 @@ async fn health_check() -> impl IntoResponse {
 
 // Helper functions
 async fn process_image_upload(
+    state: &AppState,
     mut multipart: Multipart,
-    options: AnalysisOptions
+    mut options: AnalysisOptions
 ) -> Result<AnalysisResponse, String> {
     debug!("Starting multipart processing");
-    let processor = ImageProcessor::new(AIProvider::OpenAI, Some(options.model), None);
-
-    let field = match multipart.next_field().await {
-        Ok(Some(field)) => {
-            debug!(
-                "Received field: name={:?}, filename={:?}, content_type={:?}",
-                field.name(),
-                field.file_name(),
-                field.content_type()
-            );
-            field
-        }
-        Ok(None) => {
-            let msg = "No fields found in multipart form";
-            error!(msg);
-            return Err(msg.to_string());
-        }
+    let images = read_images(&mut multipart, MAX_ANALYSIS_IMAGES, &mut options, &state.fetcher).await?;
+    analyze_images(state, images, options).await
+}
+
+/// Analyzes the upload with a category of `T`, `default_category` unless the caller asked
+/// for another one `T` handles, and takes the extraction named `what` from the response.
+async fn extract_details<T: CategoryExtraction>(
+    state: &AppState,
+    multipart: Multipart,
+    mut options: AnalysisOptions,
+    default_category: &str,
+    what: &str,
+    take: fn(AnalysisResponse) -> Option<T>
+) -> Result<T, axum::response::Response> {
+    let handled = options.category
+        .as_deref()
+        .and_then(ContentCategory::from_name)
+        .is_some_and(|category| T::handles(&category));
+    if !handled {
+        options.category = Some(default_category.to_string());
+    }
+    options.two_stage = false;
+
+    match process_image_upload(state, multipart, options).await {
+        Ok(response) =>
+            take(response).ok_or_else(|| {
+                error_response(format!("No {} could be extracted from the image", what))
+            }),
         Err(e) => {
-            let msg = format!("Failed to read multipart field: {}", e);
-            error!(msg);
-            return Err(msg);
+            error!("Failed to extract {}: {}", what, e);
+            Err(error_response(e))
         }
-    };
+    }
+}
 
-    if field.name().unwrap_or("") != "image" {
-        let msg = format!("Expected field name 'image', got {:?}", field.name());
-        error!(msg);
-        return Err(msg);
+/// Analyzes the images of an `application/json` request.
+async fn process_json_request(
+    state: &AppState,
+    request: Request,
+    mut options: AnalysisOptions
+) -> Result<AnalysisResponse, String> {
+    debug!("Starting JSON request processing");
+    let body = axum::body
+        ::to_bytes(request.into_body(), MAX_BODY_SIZE).await
+        .map_err(|e| format!("Failed to read request body: {}", e))?;
+    let request: AnalyzeRequest = serde_json
+        ::from_slice(&body)
+        .map_err(|e| format!("Invalid JSON request: {}", e))?;
+    if let Some(overrides) = request.options {
+        options.apply_overrides(overrides)?;
     }
 
-    debug!("Reading field bytes...");
-    let data = field.bytes().await.map_err(|e| {
-        let msg = format!("Failed to read field bytes: {}", e);
-        error!(msg);
-        msg
-    })?;
+    let mut sources: Vec<JsonImage> = request.image
+        .map(|data| JsonImage { data: Some(data), ..Default::default() })
+        .into_iter()
+        .chain(request.image_url.map(|url| JsonImage { url: Some(url), ..Default::default() }))
+        .collect();
+    sources.extend(request.images);
+    if sources.is_empty() {
+        return Err("No images found in request, send image, image_url or images".to_string());
+    }
+    if sources.len() > MAX_ANALYSIS_IMAGES {
+        return Err(format!("At most {} images can be sent per request", MAX_ANALYSIS_IMAGES));
+    }
 
-    if data.is_empty() {
-        let msg = "Received empty image data";
-        error!(msg);
-        return Err(msg.to_string());
+    let mut images = Vec::new();
+    let mut budget = state.fetcher.budget();
+    for source in sources {
+        let (file_name, data) = match source {
+            JsonImage { data: Some(data), url: None, path: None, file_name } =>
+                (file_name, decode_image(&data)?),
+            JsonImage { data: None, url: Some(url), path: None, file_name } => {
+                let (name, data) = fetch_image(&state.fetcher, &mut budget, &url).await?;
+                (file_name.or(name), data)
+            }
+            JsonImage { data: None, url: None, path: Some(path), file_name } => {
+                let data = read_local_image(state.local_images.as_deref().map(PathBuf::as_path), &path).await?;
+                (file_name.or(Some(path)), data)
+            }
+            _ => {
+                return Err("Each image needs exactly one of data, url or path".to_string());
+            }
+        };
+        if data.is_empty() {
+            return Err("Received empty image data".to_string());
+        }
+        images.push((file_name, data));
+    }
+    debug!("Read {} images", images.len());
+    analyze_images(state, images, options).await
+}
+
+/// Decodes a base64-encoded image, optionally given as a `data:` URL.
+fn decode_image(text: &str) -> Result<bytes::Bytes, String> {
+    let text = text.trim();
+    let encoded = match text.strip_prefix("data:") {
+        Some(url) => {
+            let (media_type, data) = url
+                .split_once(',')
+                .ok_or_else(|| "Invalid data URL, expected data:<type>;base64,<data>".to_string())?;
+            if !media_type.ends_with(";base64") {
+                return Err("Data URLs must be base64-encoded".to_string());
+            }
+            data
+        }
+        None => text,
+    };
+    let encoded: String = encoded
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map(bytes::Bytes::from)
+        .map_err(|e| format!("Invalid base64 image: {}", e))
+}
+
+/// Reads an image by its path relative to `root`, which must not lead outside of it.
+async fn read_local_image(root: Option<&Path>, path: &str) -> Result<bytes::Bytes, String> {
+    let root = root.ok_or_else(|| {
+        "Local file references are disabled, set LOCAL_IMAGES_DIR to enable them".to_string()
+    })?;
+    let relative = Path::new(path);
+    let is_plain = relative.components().all(|component| matches!(component, Component::Normal(_)));
+    if path.is_empty() || !is_plain {
+        return Err(format!("Invalid path {:?}, use a path relative to LOCAL_IMAGES_DIR", path));
     }
+    // Resolves symlinks, which could otherwise point outside of the directory
+    let full = fs
+        ::canonicalize(root.join(relative)).await
+        .map_err(|_| format!("Image {:?} not found", path))?;
+    if !full.starts_with(root) {
+        return Err(format!("Image {:?} is outside of LOCAL_IMAGES_DIR", path));
+    }
+    fs::read(&full).await
+        .map(bytes::Bytes::from)
+        .map_err(|e| format!("Failed to read image {:?}: {}", path, e))
+}
 
-    debug!("Successfully read {} bytes of image data", data.len());
+async fn analyze_images(
+    state: &AppState,
+    images: Vec<(Option<String>, bytes::Bytes)>,
+    options: AnalysisOptions
+) -> Result<AnalysisResponse, String> {
+    let processor = options.processor(state)?;
+    let objects = options.analysis_config()?.detect_objects;
+    let structured = matches!(options.prompt_format()?, PromptFormat::Json);
+    let provider = options.provider()?;
+    let params = options.generation_params()?;
+    let file_name = images[0].0.clone();
+    let data: Vec<&[u8]> = images
+        .iter()
+        .map(|(_, data)| data.as_ref())
+        .collect();
 
-    debug!("Starting image processing with {} bytes", data.len());
-    match processor.process(&data).await {
-        Ok((analysis, token_usage)) => {
-            info!("Successfully analyzed image. Token usage: {:?}", token_usage);
+    debug!("Starting image processing of {} images", data.len());
+    match processor.process_images(&data).await {
+        Ok(output) => {
+            info!("Successfully analyzed image. Token usage: {:?}", output.token_usage);
+            let mut violations = Vec::new();
+            let result = match AnalysisResult::parse(&output.analysis) {
+                Ok(result) => Some(result),
+                Err(e) if structured => {
+                    warn!("Analysis could not be parsed into a typed result: {}", e);
+                    violations = violation_messages(e);
+                    None
+                }
+                Err(_) => None,
+            };
+            let (document, charts, diagram, interface) = match (&output.category, &result) {
+                (Some(category), Some(result)) =>
+                    (
+                        typed_details(DocumentExtraction::from_result(category, result), &mut violations),
+                        typed_details(ChartExtraction::from_result(category, result), &mut violations),
+                        typed_details(DiagramExtraction::from_result(category, result), &mut violations),
+                        typed_details(InterfaceDetails::from_result(category, result), &mut violations),
+                    ),
+                _ => (None, None, None, None),
+            };
+            let detections = result
+                .as_ref()
+                .filter(|_| objects)
+                .map(|result| {
+                    Detections::from_result(
+                        result,
+                        file_name.as_deref().unwrap_or("image"),
+                        output.image_width,
+                        output.image_height
+                    )
+                });
             Ok(AnalysisResponse {
-                analysis,
-                token_usage: Some(token_usage),
+                analysis: output.analysis,
+                result,
+                violations,
+                document,
+                charts,
+                diagram,
+                interface,
+                detections,
+                repairs: (!output.repairs.is_clean()).then_some(output.repairs),
+                category: output.category,
+                prompt_version: output.prompt_version,
+                session_id: None,
+                session: Some(Session {
+                    conversation: output.conversation,
+                    provider,
+                    model: options.model,
+                    params,
+                }),
+                token_usage: Some(output.token_usage),
             })
         }
         Err(e) => {
@@ -231,3 +1797,188 @@ async fn process_image_upload(
         }
     }
 }
+
+/// Reads the `image` fields of the form with their file names, downloads the
+/// `image_url` fields, and applies the JSON `options` field, if any, to `options`.
+async fn read_images(
+    multipart: &mut Multipart,
+    max: usize,
+    options: &mut AnalysisOptions,
+    fetcher: &ImageFetcher
+) -> Result<Vec<(Option<String>, bytes::Bytes)>, String> {
+    let mut images = Vec::new();
+    let mut budget = fetcher.budget();
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("options") => {
+                let text = field
+                    .text().await
+                    .map_err(|e| format!("Failed to read field \"options\": {}", e))?;
+                options.apply_json(&text)?;
+            }
+            Ok(Some(field)) if field.name() == Some("image") => {
+                if images.len() == max {
+                    return Err(format!("At most {} images can be sent per request", max));
+                }
+                let file_name = field.file_name().map(str::to_string);
+                let data = field
+                    .bytes().await
+                    .map_err(|e| format!("Failed to read field bytes: {}", e))?;
+                if data.is_empty() {
+                    return Err("Received empty image data".to_string());
+                }
+                images.push((file_name, data));
+            }
+            Ok(Some(field)) if field.name() == Some("image_url") => {
+                if images.len() == max {
+                    return Err(format!("At most {} images can be sent per request", max));
+                }
+                let url = field
+                    .text().await
+                    .map_err(|e| format!("Failed to read field \"image_url\": {}", e))?;
+                images.push(fetch_image(fetcher, &mut budget, &url).await?);
+            }
+            Ok(Some(field)) => {
+                return Err(
+                    format!("Expected field name 'image', 'image_url' or 'options', got {:?}", field.name())
+                );
+            }
+            Ok(None) => {
+                break;
+            }
+            Err(e) => {
+                return Err(format!("Failed to read multipart field: {}", e));
+            }
+        }
+    }
+    if images.is_empty() {
+        return Err("No images found in multipart form".to_string());
+    }
+    debug!("Read {} images", images.len());
+    Ok(images)
+}
+
+/// Downloads an image, named after the last segment of its URL.
+async fn fetch_image(
+    fetcher: &ImageFetcher,
+    budget: &mut FetchBudget,
+    url: &str
+) -> Result<(Option<String>, bytes::Bytes), String> {
+    debug!("Fetching image from {}", url);
+    let data = fetcher.fetch_within(url, budget).await.map_err(|e| e.to_string())?;
+    let file_name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string);
+    Ok((file_name, bytes::Bytes::from(data)))
+}
+
+/// Keeps successfully parsed category details, adding why to `violations` when they
+/// don't match.
+fn typed_details<T>(
+    details: Option<Result<T, eyeris::ProcessorError>>,
+    violations: &mut Vec<String>
+) -> Option<T> {
+    match details? {
+        Ok(details) => Some(details),
+        Err(e) => {
+            warn!("Category details could not be parsed: {}", e);
+            violations.extend(violation_messages(e));
+            None
+        }
+    }
+}
+
+/// Each schema violation as its own message, or the error itself for other failures.
+fn violation_messages(error: eyeris::ProcessorError) -> Vec<String> {
+    match error {
+        eyeris::ProcessorError::SchemaViolation(violations) => violations,
+        other => vec![other.to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_options_override_query_options() {
+        let mut options: AnalysisOptions = serde_json::from_str(r#"{"model": "gpt-4o-mini", "skip": "detect_faces"}"#).unwrap();
+        options.apply_json(r#"{"provider": "ollama", "prompt_format": "custom", "traits": ["mood", "era"], "max_tokens": 300}"#).unwrap();
+        assert_eq!(options.model.as_deref(), Some("gpt-4o-mini"));
+        assert!(matches!(options.provider(), Ok(AIProvider::Ollama)));
+        assert!(matches!(options.prompt_format(), Ok(PromptFormat::Custom(traits)) if traits == ["mood", "era"]));
+        let config = options.analysis_config().unwrap();
+        assert!(!config.detect_faces);
+        assert!(config.custom_traits.is_empty());
+        assert_eq!(options.generation_params().unwrap().max_tokens, Some(300));
+        options.apply_json(r#"{"max_continuations": 1000}"#).unwrap();
+        assert_eq!(options.max_continuations().unwrap_err(), "max_continuations must be at most 3, got 1000");
+
+        let error = options.apply_json(r#"{"temprature": 0.5}"#).unwrap_err();
+        assert!(error.starts_with("Unknown option \"temprature\", expected one of: provider, model"));
+        options.apply_json(r#"{"prompt_format": "platform_specific"}"#).unwrap();
+        assert_eq!(options.prompt_format().unwrap_err(), "prompt_format platform_specific needs the platform option");
+        options.apply_json(r#"{"provider": "anthropic"}"#).unwrap();
+        assert!(options.provider().unwrap_err().contains("expected one of: openai, ollama"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_query_keys_are_rejected() {
+        use axum::handler::Handler;
+
+        let state = AppState {
+            prompts: None,
+            sessions: Arc::new(SessionStore::new(DEFAULT_SESSION_TTL, DEFAULT_MAX_SESSIONS)),
+            local_images: None,
+            fetcher: Arc::new(ImageFetcher::new()),
+        };
+        let body = |response: axum::response::Response| async move {
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+        let message = |response| async move {
+            let body: serde_json::Value = serde_json::from_str(&body(response).await).unwrap();
+            body["message"].as_str().unwrap().to_string()
+        };
+        let request = |uri: &str| Request::post(uri).body(axum::body::Body::empty()).unwrap();
+
+        let response = api_extract_tables.call(request("/?format=csv&tabel=1"), state.clone()).await;
+        let error = message(response).await;
+        assert!(error.starts_with("Unknown option \"tabel\", expected one of: provider, model"));
+        assert!(error.ends_with("detail, format, table"));
+
+        let response = api_analyze.call(request("/?temprature=0.5"), state.clone()).await;
+        assert!(message(response).await.starts_with("Unknown option \"temprature\""));
+
+        // Known keys get past the query to the missing upload
+        let response = api_extract_tables.call(request("/?format=csv&table=0&model=gpt-4o"), state).await;
+        assert!(body(response).await.contains("multipart"));
+    }
+
+    #[test]
+    fn test_decode_image() {
+        assert_eq!(decode_image("aW1h\nZ2U=").unwrap(), "image");
+        assert_eq!(decode_image("data:image/png;base64,aW1hZ2U=").unwrap(), "image");
+        assert!(decode_image("data:image/svg+xml,<svg/>").is_err());
+        assert!(decode_image("not base64!").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_images_stay_inside_the_directory() {
+        let root = std::env::temp_dir().join(format!("eyeris-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("scans")).unwrap();
+        std::fs::write(root.join("scans/receipt.jpg"), b"jpeg").unwrap();
+        let root = std::fs::canonicalize(&root).unwrap();
+
+        assert_eq!(read_local_image(Some(&root), "scans/receipt.jpg").await.unwrap(), "jpeg");
+        assert!(read_local_image(None, "scans/receipt.jpg").await.unwrap_err().contains("disabled"));
+        assert!(read_local_image(Some(&root), "scans/../../etc/passwd").await.unwrap_err().starts_with("Invalid path"));
+        assert!(read_local_image(Some(&root), "/etc/passwd").await.is_err());
+        assert!(read_local_image(Some(&root), "scans/missing.jpg").await.unwrap_err().contains("not found"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}